@@ -0,0 +1,371 @@
+use crate::lastfmapi::{Album, DurationSource, Track};
+use anyhow::Context;
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions, StandardTagKey, Tag};
+use symphonia::core::probe::Hint;
+
+/// Extensions of audio files with supported tags
+const AUDIO_EXTENSIONS: [&str; 9] = [
+    "mp3", "flac", "ogg", "oga", "opus", "m4a", "mp4", "aac", "wav",
+];
+
+/// Tags and duration of a single audio file
+#[derive(Debug, Default, Clone)]
+pub struct FileTrack {
+    pub artist: Option<String>,
+    pub album_artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    /// Duration in seconds
+    pub duration: i64,
+    pub mbid: Option<String>,
+    pub album_mbid: Option<String>,
+    /// Directory of the file, albums with the same name in other directories are different
+    pub dir: Option<PathBuf>,
+}
+
+/// Album artist of a compilation without an album artist tag
+const VARIOUS_ARTISTS: &str = "Various Artists";
+
+/// Find audio files in given files and directories, sorted by path
+pub fn collect_audio_files(paths: &[PathBuf]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if !path.exists() {
+            anyhow::bail!("File {} does not exist", path.display());
+        }
+        if path.is_dir() {
+            let mut entries: Vec<PathBuf> = fs::read_dir(path)
+                .with_context(|| format!("Cannot read directory {}", path.display()))?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<_, _>>()?;
+            entries.sort();
+            files.extend(collect_audio_files(&entries)?);
+        } else if is_audio_file(path) {
+            files.push(path.clone());
+        } else {
+            debug!("Ignoring non-audio file {}", path.display());
+        }
+    }
+    Ok(files)
+}
+
+fn is_audio_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Read tags and duration of an audio file
+pub fn read_audio_file(path: &Path) -> anyhow::Result<FileTrack> {
+    let file = File::open(path)?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(ext);
+    }
+    let mut probed = symphonia::default::get_probe().format(
+        &hint,
+        mss,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+
+    // Tags may be found before the container (ID3v2) or inside it
+    let mut tags: Vec<Tag> = Vec::new();
+    if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+        tags.extend_from_slice(revision.tags());
+    }
+    let mut format = probed.format;
+    if let Some(revision) = format.metadata().current() {
+        tags.extend_from_slice(revision.tags());
+    }
+
+    let mut file_track = FileTrack::default();
+    for tag in &tags {
+        // RIFF INFO values are NUL-terminated
+        let value = tag.value.to_string();
+        let value = value.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+        if value.is_empty() {
+            continue;
+        }
+        let field = match tag.std_key {
+            Some(StandardTagKey::Artist) => &mut file_track.artist,
+            Some(StandardTagKey::AlbumArtist) => &mut file_track.album_artist,
+            Some(StandardTagKey::Album) => &mut file_track.album,
+            Some(StandardTagKey::TrackTitle) => &mut file_track.title,
+            Some(StandardTagKey::MusicBrainzRecordingId) => &mut file_track.mbid,
+            Some(StandardTagKey::MusicBrainzAlbumId) => &mut file_track.album_mbid,
+            Some(StandardTagKey::TrackNumber) => {
+                file_track.track_number = file_track.track_number.or(parse_number(value));
+                continue;
+            }
+            Some(StandardTagKey::DiscNumber) => {
+                file_track.disc_number = file_track.disc_number.or(parse_number(value));
+                continue;
+            }
+            _ => continue,
+        };
+        field.get_or_insert(value.to_string());
+    }
+
+    let track = format
+        .default_track()
+        .context("No audio track in file")?
+        .clone();
+    let time_base = track.codec_params.time_base.context("Unknown time base")?;
+    let frames = match track.codec_params.n_frames {
+        Some(frames) => frames,
+        None => {
+            // Count frames in all packets when the header does not tell the length
+            let mut frames = 0;
+            while let Ok(packet) = format.next_packet() {
+                if packet.track_id() == track.id {
+                    frames += packet.dur;
+                }
+            }
+            frames
+        }
+    };
+    let time = time_base.calc_time(frames);
+    file_track.duration = (time.seconds as f64 + time.frac).round() as i64;
+    Ok(file_track)
+}
+
+/// Parse track or disc number like "3" or "3/12"
+fn parse_number(value: &str) -> Option<u32> {
+    value.split('/').next()?.trim().parse().ok()
+}
+
+/// Group file tracks into albums in the order of files, tracks are ordered by disc and number.
+/// Files are grouped by album tag and directory, by artist only when there is no album tag,
+/// so a compilation without album artist tags stays one album.
+pub fn group_albums(file_tracks: Vec<FileTrack>) -> anyhow::Result<Vec<Album>> {
+    type AlbumKey = (Option<String>, Option<PathBuf>, Option<String>);
+    let mut groups: Vec<(AlbumKey, Vec<FileTrack>)> = Vec::new();
+    let mut group_index: HashMap<AlbumKey, usize> = HashMap::new();
+    for file_track in file_tracks {
+        let artist = track_album_artist(&file_track).context("No artist tag")?;
+        let key = (
+            file_track.album.clone(),
+            file_track.dir.clone(),
+            Some(artist).filter(|_| file_track.album.is_none()),
+        );
+        let idx = *group_index.entry(key.clone()).or_insert_with(|| {
+            groups.push((key, Vec::new()));
+            groups.len() - 1
+        });
+        groups[idx].1.push(file_track);
+    }
+
+    let mut albums = Vec::with_capacity(groups.len());
+    for ((title, _, _), mut file_tracks) in groups {
+        let mut artists = file_tracks.iter().filter_map(track_album_artist);
+        let first = artists.next().unwrap_or_default();
+        let artist = if artists.all(|artist| artist == first) {
+            first
+        } else {
+            VARIOUS_ARTISTS.to_string()
+        };
+        // Stable sort keeps the path order for tracks without numbers, they go last
+        file_tracks.sort_by_key(|t| {
+            (
+                t.disc_number.unwrap_or(1),
+                t.track_number.is_none(),
+                t.track_number,
+            )
+        });
+        let mbid = file_tracks.iter().find_map(|t| t.album_mbid.clone());
+        let tracks = file_tracks
+            .into_iter()
+            .map(|file_track| {
+                Ok(Track {
+                    title: file_track.title.context("No title tag")?,
+                    artist: file_track.artist,
+                    album_artist: file_track.album_artist,
+                    duration: file_track.duration,
+                    duration_source: DurationSource::Release,
+                    number: file_track.track_number,
+                    disc: file_track.disc_number,
+                    mbid: file_track.mbid,
+                })
+            })
+            .collect::<anyhow::Result<Vec<Track>>>()
+            .with_context(|| {
+                format!(
+                    "Album '{}' of artist '{}'",
+                    title.as_deref().unwrap_or_default(),
+                    artist
+                )
+            })?;
+        albums.push(Album {
+            title,
+            artist,
+            tracks,
+            url: None,
+            mbid,
+        });
+    }
+    Ok(albums)
+}
+
+/// Album artist as tagged or the track artist
+fn track_album_artist(file_track: &FileTrack) -> Option<String> {
+    file_track
+        .album_artist
+        .clone()
+        .or_else(|| file_track.artist.clone())
+}
+
+/// Read all audio files, skipping unreadable ones with a warning
+pub fn read_audio_files(paths: &[PathBuf]) -> anyhow::Result<Vec<FileTrack>> {
+    let files = collect_audio_files(paths)?;
+    let mut file_tracks = Vec::with_capacity(files.len());
+    for file in files {
+        match read_audio_file(&file) {
+            Ok(mut file_track) => {
+                file_track.dir = file.parent().map(Path::to_path_buf);
+                debug!("Read {}: {:?}", file.display(), file_track);
+                file_tracks.push(file_track);
+            }
+            Err(e) => warn!("Cannot read tags of {}: {}", file.display(), e),
+        }
+    }
+    if file_tracks.is_empty() {
+        anyhow::bail!("No audio files found");
+    }
+    Ok(file_tracks)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use test_log::test;
+
+    /// Build a silent 8 kHz mono WAV file with RIFF INFO tags
+    fn make_wav(seconds: u32, tags: &[(&[u8; 4], &str)]) -> Vec<u8> {
+        let data_len = seconds * 8000;
+        let mut info = b"INFO".to_vec();
+        for (key, value) in tags {
+            let mut value = value.as_bytes().to_vec();
+            value.push(0);
+            if value.len() % 2 == 1 {
+                value.push(0);
+            }
+            info.extend_from_slice(*key);
+            info.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            info.extend_from_slice(&value);
+        }
+        let mut body = b"WAVE".to_vec();
+        body.extend_from_slice(b"fmt ");
+        body.extend_from_slice(&16u32.to_le_bytes());
+        body.extend_from_slice(&1u16.to_le_bytes());
+        body.extend_from_slice(&1u16.to_le_bytes());
+        body.extend_from_slice(&8000u32.to_le_bytes());
+        body.extend_from_slice(&8000u32.to_le_bytes());
+        body.extend_from_slice(&1u16.to_le_bytes());
+        body.extend_from_slice(&8u16.to_le_bytes());
+        body.extend_from_slice(b"LIST");
+        body.extend_from_slice(&(info.len() as u32).to_le_bytes());
+        body.extend_from_slice(&info);
+        body.extend_from_slice(b"data");
+        body.extend_from_slice(&data_len.to_le_bytes());
+        body.extend(std::iter::repeat_n(128u8, data_len as usize));
+        let mut wav = b"RIFF".to_vec();
+        wav.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        wav.extend_from_slice(&body);
+        wav
+    }
+
+    #[test]
+    fn test_read_audio_file() {
+        let dir = std::env::temp_dir().join(format!("scrobbler-audio-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("01 Eden.wav");
+        let wav = make_wav(
+            3,
+            &[
+                (b"IART", "Hooverphonic"),
+                (b"IPRD", "Blue Wonder Power Milk"),
+                (b"INAM", "Eden"),
+                (b"IPRT", "3/12"),
+            ],
+        );
+        fs::write(&path, wav).unwrap();
+        fs::write(dir.join("cover.jpg"), b"").unwrap();
+
+        let files = collect_audio_files(std::slice::from_ref(&dir)).unwrap();
+        assert_eq!(files, vec![path.clone()]);
+        let file_track = read_audio_file(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(file_track.artist.as_deref(), Some("Hooverphonic"));
+        assert_eq!(file_track.album.as_deref(), Some("Blue Wonder Power Milk"));
+        assert_eq!(file_track.title.as_deref(), Some("Eden"));
+        assert_eq!(file_track.track_number, Some(3));
+        assert_eq!(file_track.duration, 3);
+    }
+
+    #[test]
+    fn test_group_albums() {
+        let file_track = |album: &str, artist: &str, title: &str, disc, number| FileTrack {
+            artist: Some(artist.to_string()),
+            album_artist: Some("Various Artists".to_string()).filter(|_| album == "Mix"),
+            album: Some(album.to_string()),
+            title: Some(title.to_string()),
+            track_number: Some(number),
+            disc_number: Some(disc),
+            duration: 200,
+            ..FileTrack::default()
+        };
+        let albums = group_albums(vec![
+            file_track("Mix", "Massive Attack", "Teardrop", 1, 2),
+            file_track("Jakalope", "Hooverphonic", "Eden", 2, 1),
+            FileTrack {
+                track_number: None,
+                ..file_track("Jakalope", "Hooverphonic", "Hidden", 1, 0)
+            },
+            file_track("Jakalope", "Hooverphonic", "One", 1, 2),
+            file_track("Mix", "Portishead", "Roads", 1, 1),
+            // A compilation tagged without album artist
+            file_track("Chill", "Air", "La femme d'argent", 1, 3),
+            file_track("Chill", "Moby", "Porcelain", 1, 2),
+            file_track("Chill", "Air", "Playground Love", 1, 1),
+            FileTrack {
+                dir: Some(PathBuf::from("other")),
+                ..file_track("Chill", "Air", "Alone in Kyoto", 1, 1)
+            },
+        ])
+        .unwrap();
+        assert_eq!(albums.len(), 4);
+        assert_eq!(albums[0].artist, "Various Artists");
+        assert_eq!(albums[0].tracks[0].artist.as_deref(), Some("Portishead"));
+        assert_eq!(
+            albums[0].tracks[0].album_artist.as_deref(),
+            Some("Various Artists")
+        );
+        assert_eq!(albums[1].artist, "Hooverphonic");
+        let titles: Vec<&str> = albums[1].tracks.iter().map(|t| t.title.as_str()).collect();
+        assert_eq!(titles, vec!["One", "Hidden", "Eden"]);
+        assert_eq!(albums[1].tracks[0].album_artist, None);
+        assert_eq!(albums[2].artist, "Various Artists");
+        let titles: Vec<&str> = albums[2].tracks.iter().map(|t| t.title.as_str()).collect();
+        assert_eq!(
+            titles,
+            vec!["Playground Love", "Porcelain", "La femme d'argent"]
+        );
+        // Same album name in another directory
+        assert_eq!(albums[3].artist, "Air");
+        assert_eq!(albums[3].tracks.len(), 1);
+
+        assert!(group_albums(vec![FileTrack::default()]).is_err());
+    }
+}
@@ -1,10 +1,20 @@
-use crate::lastfmapi::LastfmApiBuilder;
+use crate::credentials::{
+    load_secrets, remove_secrets, restrict_permissions, save_secrets, write_private,
+    CredentialStore,
+};
+use crate::lastfmapi::{LastfmApi, LastfmApiBuilder, Session};
+use crate::webauth::CallbackServer;
 use anyhow::Context;
+use clap::ValueEnum;
 use directories::ProjectDirs;
-use log::info;
+use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How long to wait for the user to confirm web auth in a browser
+const CALLBACK_TIMEOUT: Duration = Duration::from_secs(300);
 
 #[derive(Serialize, Deserialize)]
 pub struct AuthConfig {
@@ -13,48 +23,301 @@ pub struct AuthConfig {
     pub session_key: String,
 }
 
-//pub fn is_authenticated() -> anyhow::Result<bool> {
-//Ok(config_file()?.is_file())
-//}
+/// Auth config as saved in a file, secrets may be kept in a credential store
+#[derive(Serialize, Deserialize)]
+struct SavedConfig {
+    #[serde(default)]
+    store: CredentialStore,
+    api_key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    secret_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    session_key: Option<String>,
+}
 
-/// Provide path to auth config file
-fn config_file() -> anyhow::Result<PathBuf> {
+/// Secrets kept in a credential store
+#[derive(Serialize, Deserialize)]
+struct Secrets {
+    secret_key: String,
+    session_key: String,
+}
+
+/// Provide path to config directory, creating it if needed
+pub fn config_dir() -> anyhow::Result<PathBuf> {
     let proj_dirs = ProjectDirs::from("ru", "omniverse", "offline-scrobbler")
         .context("cannot detect config dir")?;
     let config_path = proj_dirs.config_dir();
-    let config_file = config_path.join("config.toml");
     std::fs::create_dir_all(config_path)?;
-    info!("Using auth config file {}", config_file.display());
-    Ok(config_file.to_path_buf())
+    Ok(config_path.to_path_buf())
+}
+
+/// Named profile of a Last.fm account with its own auth config and queue
+#[derive(Debug, Clone)]
+pub struct Profile {
+    /// None for the default profile
+    name: Option<String>,
+    dir: PathBuf,
+}
+
+impl Profile {
+    /// Named profile or the default one, also named "default"
+    pub fn new(name: Option<String>) -> anyhow::Result<Self> {
+        let name = name.filter(|name| name != "default");
+        let dir = match &name {
+            None => config_dir()?,
+            Some(name) => {
+                if name.is_empty()
+                    || !name
+                        .chars()
+                        .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+                {
+                    anyhow::bail!(
+                        "Wrong profile name '{}', use letters, digits, '-' and '_'",
+                        name
+                    );
+                }
+                config_dir()?.join("profiles").join(name)
+            }
+        };
+        Ok(Self::in_dir(name, dir))
+    }
+
+    /// Profile with files in a given directory
+    pub fn in_dir(name: Option<String>, dir: PathBuf) -> Self {
+        Self { name, dir }
+    }
+
+    /// Name for logs and credential stores
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or("default")
+    }
+
+    /// Provide path to directory with files of the profile, creating it if needed
+    pub fn dir(&self) -> anyhow::Result<PathBuf> {
+        fs::create_dir_all(&self.dir)?;
+        Ok(self.dir.clone())
+    }
+
+    /// Whether the profile has saved auth config
+    pub fn is_authenticated(&self) -> bool {
+        self.dir.join("config.toml").is_file()
+    }
+
+    /// Provide path to auth config file
+    fn config_file(&self) -> anyhow::Result<PathBuf> {
+        let config_file = self.dir()?.join("config.toml");
+        info!("Using auth config file {}", config_file.display());
+        Ok(config_file)
+    }
+}
+
+/// Profiles with saved auth config, the default one goes first
+pub fn list_profiles() -> anyhow::Result<Vec<Profile>> {
+    let config_dir = config_dir()?;
+    let mut profiles = Vec::new();
+    let default = Profile::in_dir(None, config_dir.clone());
+    if default.is_authenticated() {
+        profiles.push(default);
+    }
+    let profiles_dir = config_dir.join("profiles");
+    if profiles_dir.is_dir() {
+        let mut named: Vec<Profile> = fs::read_dir(&profiles_dir)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                Profile::in_dir(Some(name), entry.path())
+            })
+            .filter(Profile::is_authenticated)
+            .collect();
+        named.sort_by(|a, b| a.name.cmp(&b.name));
+        profiles.extend(named);
+    }
+    Ok(profiles)
+}
+
+/// Run an action for every profile, a failure of one profile does not stop others
+pub fn for_each_profile(
+    profiles: &[Profile],
+    mut action: impl FnMut(usize, &Profile) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    if let [profile] = profiles {
+        return action(0, profile);
+    }
+    let mut failed = 0;
+    for (idx, profile) in profiles.iter().enumerate() {
+        info!("Using profile {}", profile.name());
+        if let Err(err) = action(idx, profile) {
+            error!("Error: {}", err);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        anyhow::bail!("Failed for {} of {} profiles", failed, profiles.len());
+    }
+    Ok(())
 }
 
 fn save_auth_config(
+    profile: &Profile,
     api_key: String,
     secret_key: String,
     session_key: String,
+    store: Option<CredentialStore>,
 ) -> anyhow::Result<()> {
-    let config = AuthConfig {
-        api_key,
-        secret_key,
-        session_key,
+    let config_file = profile.config_file().context("cannot find config file")?;
+    let previous_store = read_saved_config(&config_file)
+        .ok()
+        .map(|config| config.store);
+    // Authenticating again keeps secrets where they are unless asked otherwise
+    let store = store.or(previous_store).unwrap_or_default();
+    let previous_store = previous_store.filter(|&previous| previous != store);
+    let config = if store == CredentialStore::Plaintext {
+        SavedConfig {
+            store,
+            api_key,
+            secret_key: Some(secret_key),
+            session_key: Some(session_key),
+        }
+    } else {
+        let secrets = toml::to_string(&Secrets {
+            secret_key,
+            session_key,
+        })?;
+        save_secrets(store, profile.name(), &profile.dir()?, &secrets)?;
+        info!("Saved secrets to {:?} store", store);
+        SavedConfig {
+            store,
+            api_key,
+            secret_key: None,
+            session_key: None,
+        }
     };
     let serialized: String = toml::to_string(&config)?;
 
-    fs::write(
-        config_file().context("cannot find config file")?,
-        serialized,
-    )?;
+    write_private(&config_file, &serialized)?;
+    // Secrets must not stay behind in a store which is not used anymore
+    if let Some(previous) = previous_store {
+        match remove_secrets(previous, profile.name(), &profile.dir()?) {
+            Ok(()) => info!("Removed secrets from {:?} store", previous),
+            Err(e) => warn!("Cannot remove secrets from {:?} store: {:#}", previous, e),
+        }
+    }
     Ok(())
 }
 
-pub fn load_auth_config() -> anyhow::Result<AuthConfig> {
-    let serialized = fs::read_to_string(config_file().context("cannot find config file")?)?;
-    let config: AuthConfig = toml::from_str(&serialized)?;
+fn read_saved_config(config_file: &Path) -> anyhow::Result<SavedConfig> {
+    let serialized = fs::read_to_string(config_file)?;
+    Ok(toml::from_str(&serialized)?)
+}
+
+pub fn load_auth_config(profile: &Profile) -> anyhow::Result<AuthConfig> {
+    let config_file = profile.config_file().context("cannot find config file")?;
+    let config = read_saved_config(&config_file)?;
+
+    let (secret_key, session_key) = match config.store {
+        CredentialStore::Plaintext => {
+            if restrict_permissions(&config_file)? {
+                warn!("Config file with secrets was readable by others, restricted it to owner");
+            }
+            (
+                config.secret_key.context("No secret_key in config")?,
+                config.session_key.context("No session_key in config")?,
+            )
+        }
+        store => {
+            let secrets = load_secrets(store, profile.name(), &profile.dir()?)?;
+            let secrets: Secrets =
+                toml::from_str(&secrets).context("Cannot parse stored secrets")?;
+            (secrets.secret_key, secrets.session_key)
+        }
+    };
+    Ok(AuthConfig {
+        api_key: config.api_key,
+        secret_key,
+        session_key,
+    })
+}
+
+/// Show where credentials are kept and check the session with Last.fm
+pub fn auth_status(profile: &Profile) -> anyhow::Result<()> {
+    info!("Using profile {}", profile.name());
+    if !profile.is_authenticated() {
+        anyhow::bail!("Not authenticated, run `auth` first");
+    }
+    let config = read_saved_config(&profile.config_file()?)?;
+    if let Some(store) = config.store.to_possible_value() {
+        info!("Secrets are kept in {} store", store.get_name());
+    }
+    let auth_config = load_auth_config(profile)?;
+    let api = LastfmApiBuilder::new(auth_config).build();
+    let username = api.get_username()?;
+    info!("Session is valid for Last.fm user {}", username);
+    Ok(())
+}
 
-    Ok(config)
+/// Remove saved credentials of a profile, queued scrobbles are kept
+pub fn logout(profile: &Profile) -> anyhow::Result<()> {
+    logout_from(profile, CredentialStore::value_variants())
 }
 
-pub fn authenticate(api_key: String, secret_key: String) -> anyhow::Result<()> {
+/// Remove saved credentials of a profile also looking for stray secrets in given stores
+fn logout_from(profile: &Profile, stores: &[CredentialStore]) -> anyhow::Result<()> {
+    if !profile.is_authenticated() {
+        info!("Profile {} is not authenticated", profile.name());
+        return Ok(());
+    }
+    let config_file = profile.config_file()?;
+    let config = read_saved_config(&config_file)?;
+    // Remove secrets first so that config still points to them on failure
+    remove_secrets(config.store, profile.name(), &profile.dir()?)?;
+    // Secrets may be left in other stores by older versions, most of them are just not found
+    for &store in stores {
+        if store != config.store {
+            if let Err(e) = remove_secrets(store, profile.name(), &profile.dir()?) {
+                debug!("Nothing removed from {:?} store: {:#}", store, e);
+            }
+        }
+    }
+    fs::remove_file(&config_file)?;
+    if profile.name.is_some() {
+        // Fails if the profile still has queued scrobbles
+        let _ = fs::remove_dir(profile.dir()?);
+    }
+    info!("Removed credentials of profile {}", profile.name());
+    info!("Revoke the session key at https://www.last.fm/settings/applications if needed");
+    Ok(())
+}
+
+/// Web auth which redirects the browser to a local callback server with a token
+fn web_auth_session(
+    api: &LastfmApi,
+    api_key: &str,
+    server: &CallbackServer,
+    timeout: Duration,
+) -> anyhow::Result<Session> {
+    let callback_url = server.callback_url()?;
+    let url = format!(
+        "http://www.last.fm/api/auth/?api_key={key}&cb={cb}",
+        key = api_key,
+        cb = urlencoding::encode(&callback_url)
+    );
+    info!("Please open the URL\n{}\nand confirm permission", url);
+    info!("Waiting for Last.fm to redirect to {}", callback_url);
+    let request_token = server.wait_for_token(timeout)?;
+    info!("Got request token from redirect");
+    let session = api
+        .get_session(request_token)
+        .context("cannot get session token")?;
+    Ok(session)
+}
+
+pub fn authenticate(
+    profile: &Profile,
+    api_key: String,
+    secret_key: String,
+    store: Option<CredentialStore>,
+    callback: bool,
+) -> anyhow::Result<()> {
     let auth_config = AuthConfig {
         api_key: api_key.clone(),
         secret_key: secret_key.clone(),
@@ -62,6 +325,13 @@ pub fn authenticate(api_key: String, secret_key: String) -> anyhow::Result<()> {
     };
     let api = LastfmApiBuilder::new(auth_config).build();
 
+    if callback {
+        let server = CallbackServer::bind()?;
+        let session = web_auth_session(&api, &api_key, &server, CALLBACK_TIMEOUT)?;
+        info!("Authenticated as Last.fm user {}", &session.name);
+        return save_auth_config(profile, api_key, secret_key, session.key, store);
+    }
+
     let request_token = api.get_request_token()?;
 
     let url = format!(
@@ -77,10 +347,189 @@ pub fn authenticate(api_key: String, secret_key: String) -> anyhow::Result<()> {
         info!("Waiting done");
     }
 
-    let token = api
-        .get_session_token(request_token)
+    let session = api
+        .get_session(request_token)
         .context("cannot get session token")?;
-    info!("Got token {}", &token);
-    save_auth_config(api_key, secret_key, token)?;
+    info!("Authenticated as Last.fm user {}", &session.name);
+    save_auth_config(profile, api_key, secret_key, session.key, store)?;
     Ok(())
 }
+
+/// Authenticate with username and password read from stdin, without a browser
+pub fn authenticate_mobile(
+    profile: &Profile,
+    api_key: String,
+    secret_key: String,
+    username: String,
+    store: Option<CredentialStore>,
+) -> anyhow::Result<()> {
+    let mut password = String::new();
+    std::io::stdin()
+        .read_line(&mut password)
+        .context("cannot read password from stdin")?;
+    let password = password.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        anyhow::bail!("Empty password on stdin");
+    }
+
+    let auth_config = AuthConfig {
+        api_key: api_key.clone(),
+        secret_key: secret_key.clone(),
+        session_key: "".into(),
+    };
+    let api = LastfmApiBuilder::new(auth_config).build();
+    let session = api
+        .get_mobile_session(&username, password)
+        .context("cannot get mobile session")?;
+    info!("Authenticated as Last.fm user {}", &session.name);
+    save_auth_config(profile, api_key, secret_key, session.key, store)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use httpmock::prelude::*;
+    use test_log::test;
+
+    #[test]
+    fn test_web_auth_session() {
+        let server = MockServer::start();
+        let mock_session = server.mock(|when, then| {
+            when.method(POST)
+                .path("/2.0")
+                .x_www_form_urlencoded_tuple("method", "auth.getSession")
+                .x_www_form_urlencoded_tuple("token", "cb-token");
+            then.status(200).body(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+                <lfm status=\"ok\"><session><name>rj</name>\
+                <key>d580d57f32848f5dcf574d1ce18d78b2</key>\
+                <subscriber>0</subscriber></session></lfm>",
+            );
+        });
+        let api = LastfmApiBuilder::new(AuthConfig {
+            api_key: "key".into(),
+            secret_key: "secret".into(),
+            session_key: String::new(),
+        })
+        .with_api_host(format!("http://{}", server.address()))
+        .build();
+
+        let callback = CallbackServer::bind().unwrap();
+        let callback_url = callback.callback_url().unwrap();
+        // Play the browser redirected by Last.fm
+        let browser = std::thread::spawn(move || {
+            let client = reqwest::blocking::Client::new();
+            let root = callback_url.split("/callback/").next().unwrap();
+            assert_eq!(
+                client
+                    .get(format!("{}/favicon.ico", root))
+                    .send()
+                    .unwrap()
+                    .status(),
+                404
+            );
+            // A token is accepted only from the callback path with the nonce
+            for url in [
+                format!("{}/callback?token=stray-token", root),
+                format!("{}/callback/guess?token=stray-token", root),
+                format!("{}/?token=stray-token", root),
+                format!("{}/extra?token=stray-token", callback_url),
+            ] {
+                assert_eq!(client.get(url).send().unwrap().status(), 404);
+            }
+            let post = client.post(format!("{}?token=stray-token", callback_url));
+            assert_eq!(post.send().unwrap().status(), 404);
+            let resp = client
+                .get(format!("{}?token=cb-token", callback_url))
+                .send()
+                .unwrap();
+            assert!(resp.status().is_success());
+        });
+
+        let session = web_auth_session(&api, "key", &callback, Duration::from_secs(10)).unwrap();
+        browser.join().unwrap();
+        mock_session.assert();
+        assert_eq!(session.name, "rj");
+        assert_eq!(session.key, "d580d57f32848f5dcf574d1ce18d78b2");
+    }
+
+    /// Profile in a fresh temporary directory
+    fn temp_profile(test: &str) -> Profile {
+        let dir = std::env::temp_dir().join(format!("profile-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        Profile::in_dir(Some(test.to_string()), dir)
+    }
+
+    #[test]
+    fn test_switch_store() {
+        let profile = temp_profile("switch");
+        let dir = profile.dir().unwrap();
+        fs::write(
+            dir.join("config.toml"),
+            "store = \"age\"\napi_key = \"key\"\n",
+        )
+        .unwrap();
+        fs::write(dir.join("secrets.age"), "encrypted").unwrap();
+
+        save_auth_config(
+            &profile,
+            "key".into(),
+            "secret".into(),
+            "sk".into(),
+            Some(CredentialStore::Plaintext),
+        )
+        .unwrap();
+        assert!(!dir.join("secrets.age").exists());
+        let config = load_auth_config(&profile).unwrap();
+        assert_eq!(config.session_key, "sk");
+
+        // Without a store the recorded one is kept
+        save_auth_config(&profile, "key".into(), "secret".into(), "sk2".into(), None).unwrap();
+        let config = read_saved_config(&dir.join("config.toml")).unwrap();
+        assert_eq!(config.store, CredentialStore::Plaintext);
+        assert_eq!(config.session_key.as_deref(), Some("sk2"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_logout() {
+        let profile = temp_profile("logout");
+        let dir = profile.dir().unwrap();
+        save_auth_config(
+            &profile,
+            "key".into(),
+            "secret".into(),
+            "sk".into(),
+            Some(CredentialStore::Plaintext),
+        )
+        .unwrap();
+        // Left by an earlier age store
+        fs::write(dir.join("secrets.age"), "encrypted").unwrap();
+        assert!(profile.is_authenticated());
+
+        // System credential stores are not touched by tests
+        let stores = [CredentialStore::Plaintext, CredentialStore::Age];
+        logout_from(&profile, &stores).unwrap();
+        assert!(!profile.is_authenticated());
+        assert!(!dir.join("secrets.age").exists());
+        // A named profile without queued scrobbles is removed completely
+        assert!(!dir.exists());
+        logout_from(&profile, &stores).unwrap();
+    }
+
+    #[test]
+    fn test_saved_config() {
+        // Configs saved before credential stores have no store
+        let config: SavedConfig =
+            toml::from_str("api_key = \"key\"\nsecret_key = \"secret\"\nsession_key = \"sk\"\n")
+                .unwrap();
+        assert_eq!(config.store, CredentialStore::Plaintext);
+        assert_eq!(config.session_key.as_deref(), Some("sk"));
+
+        let config: SavedConfig =
+            toml::from_str("store = \"secret-service\"\napi_key = \"key\"\n").unwrap();
+        assert_eq!(config.store, CredentialStore::SecretService);
+        assert_eq!(config.secret_key, None);
+    }
+}
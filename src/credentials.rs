@@ -0,0 +1,220 @@
+use anyhow::Context;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Name of the age-encrypted file with secrets in a profile directory
+const AGE_FILE: &str = "secrets.age";
+
+/// Where secret and session keys are kept
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum CredentialStore {
+    /// Config file readable only by the owner
+    #[default]
+    Plaintext,
+    /// freedesktop Secret Service, requires secret-tool
+    SecretService,
+    /// Linux kernel keyring which is cleared on reboot, requires keyctl
+    Keyring,
+    /// File encrypted with a passphrase, requires age
+    Age,
+}
+
+/// Save secrets of a profile to an external store
+pub fn save_secrets(
+    store: CredentialStore,
+    profile: &str,
+    dir: &Path,
+    secrets: &str,
+) -> anyhow::Result<()> {
+    match store {
+        CredentialStore::Plaintext => anyhow::bail!("Plaintext secrets are kept in config"),
+        CredentialStore::SecretService => {
+            let label = format!("offline-scrobbler ({})", profile);
+            run_tool(
+                "secret-tool",
+                &[
+                    "store",
+                    "--label",
+                    &label,
+                    "application",
+                    "offline-scrobbler",
+                    "profile",
+                    profile,
+                ],
+                Some(secrets),
+            )?;
+        }
+        CredentialStore::Keyring => {
+            run_tool(
+                "keyctl",
+                &["padd", "user", &key_description(profile), "@u"],
+                Some(secrets),
+            )?;
+        }
+        CredentialStore::Age => {
+            let path = dir.join(AGE_FILE);
+            // age asks for a passphrase on the terminal
+            run_tool(
+                "age",
+                &["--passphrase", "--output", &path.to_string_lossy()],
+                Some(secrets),
+            )?;
+            restrict_permissions(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Load secrets of a profile from an external store
+pub fn load_secrets(store: CredentialStore, profile: &str, dir: &Path) -> anyhow::Result<String> {
+    match store {
+        CredentialStore::Plaintext => anyhow::bail!("Plaintext secrets are kept in config"),
+        CredentialStore::SecretService => run_tool(
+            "secret-tool",
+            &[
+                "lookup",
+                "application",
+                "offline-scrobbler",
+                "profile",
+                profile,
+            ],
+            None,
+        ),
+        CredentialStore::Keyring => {
+            let key_id = run_tool(
+                "keyctl",
+                &["search", "@u", "user", &key_description(profile)],
+                None,
+            )
+            .context("No secrets in kernel keyring, it is cleared on reboot, run `auth` again")?;
+            run_tool("keyctl", &["pipe", key_id.trim()], None)
+        }
+        CredentialStore::Age => run_tool(
+            "age",
+            &["--decrypt", &dir.join(AGE_FILE).to_string_lossy()],
+            None,
+        ),
+    }
+}
+
+/// Remove secrets of a profile from an external store
+pub fn remove_secrets(store: CredentialStore, profile: &str, dir: &Path) -> anyhow::Result<()> {
+    match store {
+        CredentialStore::Plaintext => {}
+        CredentialStore::SecretService => {
+            run_tool(
+                "secret-tool",
+                &[
+                    "clear",
+                    "application",
+                    "offline-scrobbler",
+                    "profile",
+                    profile,
+                ],
+                None,
+            )?;
+        }
+        CredentialStore::Keyring => {
+            // Keys are gone after reboot
+            if let Ok(key_id) = run_tool(
+                "keyctl",
+                &["search", "@u", "user", &key_description(profile)],
+                None,
+            ) {
+                run_tool("keyctl", &["unlink", key_id.trim(), "@u"], None)?;
+            }
+        }
+        CredentialStore::Age => {
+            let path = dir.join(AGE_FILE);
+            if path.is_file() {
+                fs::remove_file(path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn key_description(profile: &str) -> String {
+    format!("offline-scrobbler:{}", profile)
+}
+
+/// Run an external tool passing input to stdin and returning stdout
+fn run_tool(program: &str, args: &[&str], input: Option<&str>) -> anyhow::Result<String> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Cannot run {}, is it installed?", program))?;
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(input.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        anyhow::bail!("{} {} failed with {}", program, args[0], output.status);
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Write a file readable only by the owner
+pub fn write_private(path: &Path, content: &str) -> anyhow::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    // Mode is applied only to new files
+    restrict_permissions(path)?;
+    file.write_all(content.as_bytes())?;
+    Ok(())
+}
+
+/// Make an existing file readable only by the owner, returns true if it was not
+pub fn restrict_permissions(path: &Path) -> anyhow::Result<bool> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(path)?.permissions().mode();
+        if mode & 0o077 != 0 {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    #[cfg(unix)]
+    fn test_write_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("scrobbler-private-{}", std::process::id()));
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(restrict_permissions(&path).unwrap());
+        assert!(!restrict_permissions(&path).unwrap());
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        write_private(&path, "secret").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        let content = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(content, "secret");
+    }
+}
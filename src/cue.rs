@@ -0,0 +1,198 @@
+use crate::lastfmapi::{Album, DurationSource, Track};
+use anyhow::Context;
+use log::warn;
+
+/// CUE sheet timestamps are in frames, 75 frames per second
+const FRAMES_PER_SECOND: i64 = 75;
+
+/// A track as described in a CUE sheet
+#[derive(Debug, Default)]
+struct CueTrack {
+    number: u32,
+    title: Option<String>,
+    performer: Option<String>,
+    /// Index of the FILE entry the track belongs to
+    file: usize,
+    /// Start of INDEX 01 in frames
+    start: Option<i64>,
+}
+
+/// Parse a CUE sheet into an album.
+/// Duration of a track which is last in its file cannot be computed from
+/// INDEX offsets, so `last_duration` seconds are used for it.
+pub fn parse_cue(content: &str, last_duration: i64) -> anyhow::Result<Album> {
+    let mut album_title: Option<String> = None;
+    let mut album_performer: Option<String> = None;
+    let mut disc: Option<u32> = None;
+    let mut file_count = 0;
+    let mut tracks: Vec<CueTrack> = Vec::new();
+
+    for (idx, line) in content.lines().enumerate() {
+        let line = line.trim_start_matches('\u{feff}').trim();
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        let context = || format!("Line {}: {}", idx + 1, line);
+        match command.to_uppercase().as_str() {
+            "REM" => {
+                if let Some(("DISCNUMBER", number)) = rest.split_once(char::is_whitespace) {
+                    disc = unquote(number.trim()).parse().ok();
+                }
+            }
+            "FILE" => file_count += 1,
+            "TRACK" => {
+                let number = rest
+                    .split_whitespace()
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .with_context(context)?;
+                tracks.push(CueTrack {
+                    number,
+                    file: file_count,
+                    ..CueTrack::default()
+                });
+            }
+            "TITLE" => match tracks.last_mut() {
+                Some(track) => track.title = Some(unquote(rest)),
+                None => album_title = Some(unquote(rest)),
+            },
+            "PERFORMER" => match tracks.last_mut() {
+                Some(track) => track.performer = Some(unquote(rest)),
+                None => album_performer = Some(unquote(rest)),
+            },
+            "INDEX" => {
+                let mut parts = rest.split_whitespace();
+                let index_number = parts.next().with_context(context)?;
+                let frames = parts
+                    .next()
+                    .and_then(parse_cue_time)
+                    .with_context(context)?;
+                if index_number == "01" {
+                    tracks
+                        .last_mut()
+                        .context("INDEX before TRACK")
+                        .with_context(context)?
+                        .start = Some(frames);
+                }
+            }
+            _ => {}
+        }
+    }
+    if tracks.is_empty() {
+        anyhow::bail!("No tracks in CUE sheet");
+    }
+
+    let artist = album_performer
+        .or_else(|| tracks.iter().find_map(|track| track.performer.clone()))
+        .context("No PERFORMER in CUE sheet")?;
+    let title = album_title.context("No album TITLE in CUE sheet")?;
+
+    let mut album_tracks = Vec::with_capacity(tracks.len());
+    for (idx, track) in tracks.iter().enumerate() {
+        let next = tracks.get(idx + 1).filter(|next| next.file == track.file);
+        let (duration, duration_source) = match (track.start, next.and_then(|next| next.start)) {
+            (Some(start), Some(next_start)) if next_start > start => (
+                (next_start - start) / FRAMES_PER_SECOND,
+                DurationSource::Release,
+            ),
+            _ => {
+                warn!(
+                    "Cannot compute duration of track #{}, assuming {} seconds",
+                    track.number, last_duration
+                );
+                (last_duration, DurationSource::Default)
+            }
+        };
+        album_tracks.push(Track {
+            title: track
+                .title
+                .clone()
+                .with_context(|| format!("No TITLE for track #{}", track.number))?,
+            artist: track.performer.clone(),
+            album_artist: None,
+            duration,
+            duration_source,
+            number: Some(track.number),
+            disc,
+            mbid: None,
+        });
+    }
+
+    Ok(Album {
+        title: Some(title),
+        artist,
+        tracks: album_tracks,
+        url: None,
+        mbid: None,
+    })
+}
+
+/// Remove surrounding quotes from a value
+fn unquote(value: &str) -> String {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+        .to_string()
+}
+
+/// Parse mm:ss:ff time into frames
+fn parse_cue_time(value: &str) -> Option<i64> {
+    let parts: Vec<i64> = value
+        .split(':')
+        .map(|p| p.parse().ok())
+        .collect::<Option<Vec<i64>>>()?;
+    match parts[..] {
+        [minutes, seconds, frames] => Some((minutes * 60 + seconds) * FRAMES_PER_SECOND + frames),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn test_parse_cue() {
+        let content = "\u{feff}REM GENRE Trip-Hop\n\
+            REM DISCNUMBER 2\n\
+            PERFORMER \"Hooverphonic\"\n\
+            TITLE \"Blue Wonder Power Milk\"\n\
+            FILE \"album.flac\" WAVE\n\
+            \x20 TRACK 01 AUDIO\n\
+            \x20   TITLE \"Battersea\"\n\
+            \x20   PERFORMER \"Hooverphonic\"\n\
+            \x20   INDEX 01 00:00:00\n\
+            \x20 TRACK 02 AUDIO\n\
+            \x20   TITLE \"One\"\n\
+            \x20   INDEX 00 04:01:50\n\
+            \x20   INDEX 01 04:02:00\n\
+            \x20 TRACK 03 AUDIO\n\
+            \x20   TITLE \"Eden\"\n\
+            \x20   INDEX 01 07:40:37\n";
+        let album = parse_cue(content, 300).unwrap();
+        assert_eq!(album.title.as_deref(), Some("Blue Wonder Power Milk"));
+        assert_eq!(album.artist, "Hooverphonic");
+        assert_eq!(album.tracks.len(), 3);
+        assert_eq!(album.tracks[0].title, "Battersea");
+        assert_eq!(album.tracks[0].duration, 242);
+        assert_eq!(album.tracks[1].duration, 218);
+        assert_eq!(album.tracks[2].duration, 300);
+        assert_eq!(album.tracks[2].number, Some(3));
+        assert_eq!(album.tracks[2].disc, Some(2));
+    }
+
+    #[test]
+    fn test_parse_cue_errors() {
+        assert!(parse_cue("PERFORMER \"Hooverphonic\"\nTITLE \"Album\"\n", 300).is_err());
+        assert!(parse_cue("TITLE \"Album\"\nTRACK 01 AUDIO\nTITLE \"One\"\n", 300).is_err());
+        assert!(parse_cue(
+            "PERFORMER \"A\"\nTITLE \"B\"\nTRACK 01 AUDIO\nINDEX 01 xx\n",
+            300
+        )
+        .is_err());
+        assert_eq!(parse_cue_time("01:02:03"), Some((62 * 75) + 3));
+        assert_eq!(parse_cue_time("01:02"), None);
+    }
+}
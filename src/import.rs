@@ -0,0 +1,286 @@
+use crate::lastfmapi::Scrobble;
+use crate::limits::check_timestamp;
+use crate::utils::parse_datetime;
+use anyhow::Context;
+use clap::ValueEnum;
+use serde_json::Value;
+use std::collections::HashMap;
+use time::{Duration, OffsetDateTime};
+
+/// Format of a listening log to import
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ImportFormat {
+    /// Comma-separated values with a header row
+    Csv,
+    /// JSON array of objects
+    Json,
+    /// One JSON object per line
+    Jsonl,
+}
+
+/// Names of columns or keys holding scrobble fields
+#[derive(Debug, Clone)]
+pub struct ColumnMapping {
+    pub artist: String,
+    pub track: String,
+    pub album: String,
+    /// Absolute time as unix timestamp, RFC3339 or local date-time
+    pub timestamp: String,
+    /// Time ago relative to now like "2h 30m", used without timestamp
+    pub offset: String,
+    /// Duration in seconds or like "4m 12s"
+    pub duration: String,
+}
+
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        ColumnMapping {
+            artist: "artist".to_string(),
+            track: "track".to_string(),
+            album: "album".to_string(),
+            timestamp: "timestamp".to_string(),
+            offset: "offset".to_string(),
+            duration: "duration".to_string(),
+        }
+    }
+}
+
+/// A row of a listening log with lowercase column names
+type Record = HashMap<String, String>;
+
+/// Read rows of a listening log
+pub fn read_records(
+    content: &str,
+    format: ImportFormat,
+    delimiter: char,
+) -> anyhow::Result<Vec<Record>> {
+    let content = content.trim_start_matches('\u{feff}');
+    match format {
+        ImportFormat::Csv => {
+            let mut rows = parse_csv(content, delimiter)?.into_iter();
+            let header: Vec<String> = rows
+                .next()
+                .context("No header row in CSV")?
+                .iter()
+                .map(|name| name.trim().to_lowercase())
+                .collect();
+            Ok(rows
+                .map(|row| header.iter().cloned().zip(row).collect())
+                .collect())
+        }
+        ImportFormat::Json => {
+            let values: Vec<Value> =
+                serde_json::from_str(content).context("Expected a JSON array of objects")?;
+            values
+                .iter()
+                .enumerate()
+                .map(|(idx, value)| json_record(value).with_context(|| format!("Row {}", idx + 1)))
+                .collect()
+        }
+        ImportFormat::Jsonl => content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(idx, line)| {
+                serde_json::from_str(line)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|value| json_record(&value))
+                    .with_context(|| format!("Line {}", idx + 1))
+            })
+            .collect(),
+    }
+}
+
+/// Split CSV into rows of fields, quoted fields may contain delimiters and newlines
+fn parse_csv(content: &str, delimiter: char) -> anyhow::Result<Vec<Vec<String>>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                '"' => quoted = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => quoted = true,
+            '\r' => {}
+            '\n' => {
+                row.push(std::mem::take(&mut field));
+                // Skip blank lines
+                if row.len() > 1 || !row[0].is_empty() {
+                    rows.push(std::mem::take(&mut row));
+                } else {
+                    row.clear();
+                }
+            }
+            c if c == delimiter => row.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        anyhow::bail!("Unterminated quoted field in CSV");
+    }
+    if !row.is_empty() || !field.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+/// Convert a JSON object into a record keeping only scalar values
+fn json_record(value: &Value) -> anyhow::Result<Record> {
+    let object = value.as_object().context("Expected a JSON object")?;
+    Ok(object
+        .iter()
+        .filter_map(|(key, value)| {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                Value::Number(n) => n.to_string(),
+                Value::Bool(b) => b.to_string(),
+                _ => return None,
+            };
+            Some((key.trim().to_lowercase(), value))
+        })
+        .collect())
+}
+
+/// Build a scrobble from a record, checking every field
+pub fn parse_record(
+    record: &Record,
+    mapping: &ColumnMapping,
+    now: OffsetDateTime,
+) -> anyhow::Result<Scrobble> {
+    let field = |name: &str| {
+        record
+            .get(&name.to_lowercase())
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+    };
+
+    let artist = field(&mapping.artist)
+        .with_context(|| format!("Empty artist in column '{}'", mapping.artist))?;
+    let track = field(&mapping.track)
+        .with_context(|| format!("Empty track in column '{}'", mapping.track))?;
+    let when = if let Some(timestamp) = field(&mapping.timestamp) {
+        match timestamp.parse::<i64>() {
+            Ok(unix) => OffsetDateTime::from_unix_timestamp(unix)?,
+            Err(_) => parse_datetime(timestamp)?,
+        }
+    } else if let Some(offset) = field(&mapping.offset) {
+        now - Duration::seconds(parse_seconds(offset).context("Wrong offset")?)
+    } else {
+        anyhow::bail!(
+            "No time in columns '{}' or '{}'",
+            mapping.timestamp,
+            mapping.offset
+        );
+    };
+    if let Some(violation) = check_timestamp(when, now) {
+        anyhow::bail!("Timestamp {}", violation);
+    }
+    let duration = field(&mapping.duration)
+        .map(|duration| parse_seconds(duration).context("Wrong duration"))
+        .transpose()?;
+
+    Ok(Scrobble {
+        album: field(&mapping.album).map(String::from),
+        duration,
+        ..Scrobble::new(artist.to_string(), track.to_string(), when)
+    })
+}
+
+/// Parse seconds like "252" or a human-readable duration like "4m 12s"
+fn parse_seconds(value: &str) -> anyhow::Result<i64> {
+    match value.parse::<i64>() {
+        Ok(seconds) if seconds >= 0 => Ok(seconds),
+        Ok(_) => anyhow::bail!("Negative value {}", value),
+        Err(_) => Ok(humantime::parse_duration(value)?.as_secs() as i64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use test_log::test;
+    use time::macros::datetime;
+
+    #[test]
+    fn test_read_records() {
+        let content = "Artist,Track,Album\n\
+            Hooverphonic,Eden,\"Blue Wonder Power Milk\"\r\n\
+            \n\
+            \"Crosby, Stills & Nash\",\"Long Time \"\"Gone\"\"\",\n";
+        let records = read_records(content, ImportFormat::Csv, ',').unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["album"], "Blue Wonder Power Milk");
+        assert_eq!(records[1]["artist"], "Crosby, Stills & Nash");
+        assert_eq!(records[1]["track"], "Long Time \"Gone\"");
+        assert!(read_records("artist\n\"Eden", ImportFormat::Csv, ',').is_err());
+
+        let content = "{\"artist\": \"Hooverphonic\", \"timestamp\": 1760601600}\n\n[1]\n";
+        let err = read_records(content, ImportFormat::Jsonl, ',').unwrap_err();
+        assert_eq!(err.to_string(), "Line 3");
+        let records =
+            read_records("[{\"Artist\": \"Hooverphonic\"}]", ImportFormat::Json, ',').unwrap();
+        assert_eq!(records[0]["artist"], "Hooverphonic");
+    }
+
+    #[test]
+    fn test_parse_record() {
+        let now = datetime!(2026-10-16 12:00 UTC);
+        let mapping = ColumnMapping {
+            track: "title".to_string(),
+            ..ColumnMapping::default()
+        };
+        let record = |fields: &[(&str, &str)]| -> Record {
+            fields
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+
+        let scrobble = parse_record(
+            &record(&[
+                ("artist", "Hooverphonic"),
+                ("title", "Eden"),
+                ("timestamp", "2026-10-16T10:00:00Z"),
+                ("duration", "4m 1s"),
+            ]),
+            &mapping,
+            now,
+        )
+        .unwrap();
+        assert_eq!(scrobble.track, "Eden");
+        assert_eq!(scrobble.when, datetime!(2026-10-16 10:00 UTC));
+        assert_eq!(scrobble.duration, Some(241));
+        assert_eq!(scrobble.album, None);
+
+        let scrobble = parse_record(
+            &record(&[("artist", "A"), ("title", "B"), ("offset", "2h")]),
+            &mapping,
+            now,
+        )
+        .unwrap();
+        assert_eq!(scrobble.when, datetime!(2026-10-16 10:00 UTC));
+
+        let errors = [
+            record(&[("artist", "A"), ("title", "B")]),
+            record(&[("artist", ""), ("title", "B"), ("offset", "2h")]),
+            record(&[("artist", "A"), ("title", "B"), ("timestamp", "1000")]),
+            record(&[("artist", "A"), ("title", "B"), ("offset", "-5")]),
+        ];
+        for record in errors {
+            assert!(parse_record(&record, &mapping, now).is_err());
+        }
+    }
+}
@@ -1,7 +1,10 @@
-use log::{debug, error, info};
-use reqwest::blocking::Client;
+use log::{debug, error, info, warn};
+use reqwest::blocking::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
+use std::fmt;
+use std::thread;
 use time::OffsetDateTime;
 use xmltree::Element;
 
@@ -9,11 +12,57 @@ use crate::auth::AuthConfig;
 
 const AUDIOSCROBBLER_HOST: &str = "https://ws.audioscrobbler.com";
 
+/// Track duration in seconds to use when it is unknown
+pub const DEFAULT_TRACK_DURATION: i64 = 300;
+
+/// Maximum number of scrobbles accepted by a single track.scrobble call
+pub const MAX_SCROBBLE_BATCH: usize = 50;
+
 /// Last.fm API client
 pub struct LastfmApi {
     auth_config: AuthConfig,
     client: Client,
     api_host: String,
+    retry_policy: RetryPolicy,
+}
+
+/// Retry policy for transient API failures
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total number of attempts including the first one
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled on every next one
+    pub initial_delay: std::time::Duration,
+    /// Upper bound of a delay between attempts
+    pub max_delay: std::time::Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_delay: std::time::Duration::from_secs(1),
+            max_delay: std::time::Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Exponential backoff with jitter before the next attempt
+    fn delay(&self, attempt: u32, err: &ApiError) -> std::time::Duration {
+        // Back off harder when Last.fm explicitly asks to slow down
+        let factor = if matches!(err, ApiError::RateLimit(_)) {
+            4
+        } else {
+            1
+        };
+        let backoff = self
+            .initial_delay
+            .saturating_mul(factor)
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay);
+        backoff.mul_f64(0.5 + fastrand::f64() / 2.0)
+    }
 }
 
 /// Last.fm API and scrobbling errors
@@ -21,34 +70,263 @@ pub struct LastfmApi {
 pub enum ApiError {
     #[error("generic: {0}")]
     Generic(String),
+    #[error("network: {0}")]
+    Network(String),
+    /// 5xx response without a Last.fm error, e.g. from a proxy in front of the API
+    #[error("server error: {0}")]
+    Server(String),
     #[error("json error")]
     Json,
     #[error("parse error: {0}")]
     Parse(String),
     #[error("unscrobbled: {0}")]
     Unscrobbled(String),
+    #[error("authentication failed: {0}")]
+    AuthenticationFailed(String),
+    #[error("operation failed: {0}")]
+    OperationFailed(String),
+    #[error("invalid session key: {0}")]
+    InvalidSession(String),
+    #[error("invalid API key: {0}")]
+    InvalidApiKey(String),
+    #[error("service offline: {0}")]
+    ServiceOffline(String),
+    #[error("invalid method signature: {0}")]
+    InvalidSignature(String),
+    #[error("temporarily unavailable: {0}")]
+    TemporarilyUnavailable(String),
+    #[error("rate limit exceeded: {0}")]
+    RateLimit(String),
+    #[error("Last.fm error {code}: {message}")]
+    Lastfm { code: i64, message: String },
+}
+
+/// Where a track duration comes from
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DurationSource {
+    /// Listed with the release: album info, tags, CUE sheet or playlist
+    #[default]
+    Release,
+    /// Last.fm track.getInfo
+    TrackInfo,
+    /// MusicBrainz recording
+    MusicBrainz,
+    /// Not known, a default is used
+    Default,
+}
+
+impl fmt::Display for DurationSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            DurationSource::Release => "release",
+            DurationSource::TrackInfo => "track.getInfo",
+            DurationSource::MusicBrainz => "MusicBrainz",
+            DurationSource::Default => "default",
+        })
+    }
 }
 
 #[derive(Debug)]
 pub struct Track {
     pub title: String,
+    /// Track artist, the album artist is used if unknown
+    pub artist: Option<String>,
+    /// Album artist like "Various Artists" for compilations
+    pub album_artist: Option<String>,
     pub duration: i64,
+    pub duration_source: DurationSource,
+    pub number: Option<u32>,
+    /// Disc of a multi-disc release
+    pub disc: Option<u32>,
+    pub mbid: Option<String>,
 }
 
 #[derive(Debug)]
 pub struct Album {
-    pub title: String,
+    /// None for playlists and files without an album tag
+    pub title: Option<String>,
+    pub artist: String,
     pub tracks: Vec<Track>,
     pub url: Option<String>,
+    pub mbid: Option<String>,
+}
+
+/// Details of a track from track.getInfo
+#[derive(Debug)]
+pub struct TrackInfo {
+    /// Duration in seconds if known
+    pub duration: Option<i64>,
+    pub mbid: Option<String>,
+}
+
+/// A session of an authenticated user, not Debug to keep the key out of logs
+pub struct Session {
+    /// Last.fm username
+    pub name: String,
+    /// Session key, a secret which must not be logged
+    pub key: String,
+}
+
+/// An album found by album.search
+#[derive(Debug)]
+pub struct AlbumMatch {
+    pub title: String,
+    pub artist: String,
+}
+
+impl Album {
+    /// Number of distinct discs, tracks without a disc are on the first one
+    pub fn disc_count(&self) -> usize {
+        let mut discs: Vec<u32> = self
+            .tracks
+            .iter()
+            .map(|track| track.disc.unwrap_or(1))
+            .collect();
+        discs.sort_unstable();
+        discs.dedup();
+        discs.len()
+    }
+}
+
+impl ApiError {
+    /// Map a Last.fm error code to an error
+    /// See https://www.last.fm/api/errorcodes
+    pub fn from_code(code: i64, message: String) -> Self {
+        match code {
+            4 => ApiError::AuthenticationFailed(message),
+            8 => ApiError::OperationFailed(message),
+            9 => ApiError::InvalidSession(message),
+            10 => ApiError::InvalidApiKey(message),
+            11 => ApiError::ServiceOffline(message),
+            13 => ApiError::InvalidSignature(message),
+            16 => ApiError::TemporarilyUnavailable(message),
+            29 => ApiError::RateLimit(message),
+            _ => ApiError::Lastfm { code, message },
+        }
+    }
+
+    /// Whether the same request may succeed if repeated later
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ApiError::Network(_)
+                | ApiError::Server(_)
+                | ApiError::OperationFailed(_)
+                | ApiError::ServiceOffline(_)
+                | ApiError::TemporarilyUnavailable(_)
+                | ApiError::RateLimit(_)
+        )
+    }
+}
+
+/// Extract an error from a failed JSON or XML response body
+fn parse_error_response(response_text: &str) -> Option<ApiError> {
+    // JSON format: {"error": 9, "message": "Invalid session key"}
+    if let Ok(resp) = serde_json::from_str::<Value>(response_text) {
+        let code = resp.get("error")?.as_i64()?;
+        let message = resp
+            .get("message")
+            .and_then(|m| m.as_str())
+            .unwrap_or_default()
+            .to_string();
+        return Some(ApiError::from_code(code, message));
+    }
+    // XML format: <lfm status="failed"><error code="9">Invalid session key</error></lfm>
+    let elem_root = Element::parse(response_text.as_bytes()).ok()?;
+    if elem_root.attributes.get("status").map(|s| s.as_str()) != Some("failed") {
+        return None;
+    }
+    let elem_error = elem_root.get_child("error")?;
+    let code = elem_error.attributes.get("code")?.parse().ok()?;
+    let message = elem_error
+        .get_text()
+        .map_or(String::new(), |t| t.trim().to_string());
+    Some(ApiError::from_code(code, message))
+}
+
+/// A single scrobble to be sent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scrobble {
+    pub artist: String,
+    pub track: String,
+    #[serde(rename = "timestamp", with = "time::serde::timestamp")]
+    pub when: OffsetDateTime,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album_artist: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub track_number: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mbid: Option<String>,
+}
+
+impl Scrobble {
+    /// Scrobble with no optional metadata
+    pub fn new(artist: String, track: String, when: OffsetDateTime) -> Self {
+        Self {
+            artist,
+            track,
+            when,
+            album: None,
+            album_artist: None,
+            track_number: None,
+            duration: None,
+            mbid: None,
+        }
+    }
+
+    /// track.scrobble parameters of this scrobble, optional ones only if set
+    fn params(&self) -> Vec<(&'static str, String)> {
+        [
+            ("artist", Some(self.artist.clone())),
+            ("track", Some(self.track.clone())),
+            ("timestamp", Some(self.when.unix_timestamp().to_string())),
+            ("album", self.album.clone()),
+            ("albumArtist", self.album_artist.clone()),
+            ("trackNumber", self.track_number.map(|n| n.to_string())),
+            ("duration", self.duration.map(|d| d.to_string())),
+            ("mbid", self.mbid.clone()),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| value.map(|v| (key, v)))
+        .collect()
+    }
+}
+
+/// Outcome of a single scrobble in a batch
+#[derive(Debug, PartialEq)]
+pub enum ScrobbleStatus {
+    Accepted,
+    Ignored(String),
+}
+
+/// How far sending scrobbles in several requests got
+#[derive(Debug)]
+pub struct BatchOutcome {
+    /// Statuses of sent scrobbles in the same order, unsent scrobbles follow them
+    pub statuses: Vec<ScrobbleStatus>,
+    /// Error which stopped sending
+    pub error: Option<ApiError>,
+}
+
+impl BatchOutcome {
+    /// Number of scrobbles sent before an error, also the index of the first unsent one
+    pub fn sent(&self) -> usize {
+        self.statuses.len()
+    }
 }
 
 impl LastfmApi {
-    pub fn new(auth_config: AuthConfig, api_host: String) -> Self {
+    pub fn new(auth_config: AuthConfig, api_host: String, retry_policy: RetryPolicy) -> Self {
         let client = Client::new();
         Self {
             auth_config,
             client,
             api_host,
+            retry_policy,
         }
     }
 
@@ -58,21 +336,8 @@ impl LastfmApi {
             api_host = self.api_host,
             key = self.auth_config.api_key
         );
-        let response = self
-            .client
-            .post(url)
-            .body("")
-            .send()
-            .map_err(|e| ApiError::Generic(e.to_string()))?;
-
-        if !response.status().is_success() {
-            error!(
-                "Error response to auth.gettoken: {}",
-                response.text().unwrap_or("".to_string())
-            );
-            return Err(ApiError::Generic("Unsuccessfull request".into()));
-        }
-        let resp: serde_json::Value = response.json().unwrap();
+        let response_text = self.execute("auth.gettoken", self.client.post(url).body(""))?;
+        let resp: Value = serde_json::from_str(&response_text).map_err(|_| ApiError::Json)?;
         debug!("Resp {}", resp);
         let token = resp
             .as_object()
@@ -85,13 +350,67 @@ impl LastfmApi {
         Ok(token)
     }
 
-    fn compute_signature(&self, params: &HashMap<&str, String>) -> String {
+    /// Send a request and return response body, retrying transient failures
+    fn execute(&self, method_name: &str, request: RequestBuilder) -> Result<String, ApiError> {
+        let mut attempt = 1;
+        loop {
+            let attempt_request = request
+                .try_clone()
+                .ok_or(ApiError::Generic("Request cannot be repeated".into()))?;
+            match self.execute_once(method_name, attempt_request) {
+                Err(e) if e.is_retryable() && attempt < self.retry_policy.max_attempts => {
+                    let delay = self.retry_policy.delay(attempt, &e);
+                    warn!(
+                        "Attempt {} of {} to {} failed: {}, retrying in {:.1}s",
+                        attempt,
+                        self.retry_policy.max_attempts,
+                        method_name,
+                        e,
+                        delay.as_secs_f64()
+                    );
+                    thread::sleep(delay);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Send a request once and return response body, mapping failures to errors
+    fn execute_once(&self, method_name: &str, request: RequestBuilder) -> Result<String, ApiError> {
+        let response = request
+            .send()
+            .map_err(|e| ApiError::Network(e.to_string()))?;
+
+        let status = response.status();
+        let response_text = response
+            .text()
+            .map_err(|e| ApiError::Network(e.to_string()))?;
+        if let Some(err) = parse_error_response(&response_text) {
+            error!("Error response to {}: {}", method_name, response_text);
+            return Err(err);
+        }
+        if status.is_server_error() {
+            error!("Error response to {}: {}", method_name, status);
+            return Err(ApiError::Server(status.to_string()));
+        }
+        if !status.is_success() {
+            error!("Error response to {}: {}", method_name, response_text);
+            return Err(ApiError::Generic("Unsuccessfull request".into()));
+        }
+        Ok(response_text)
+    }
+
+    fn compute_signature<K: AsRef<str>>(&self, params: &HashMap<K, String>) -> String {
         let mut buf = String::new();
-        let mut keys: Vec<&str> = params.keys().copied().collect();
-        keys.sort();
-        for key in keys {
+        let mut pairs: Vec<(&str, &String)> = params
+            .iter()
+            .map(|(key, value)| (key.as_ref(), value))
+            .collect();
+        pairs.sort();
+        for (key, value) in pairs {
             buf.push_str(key);
-            buf.push_str(params.get(key).unwrap());
+            buf.push_str(value);
         }
         buf.push_str(&self.auth_config.secret_key);
 
@@ -100,7 +419,7 @@ impl LastfmApi {
         md5_hex
     }
 
-    pub fn get_session_token(&self, request_token: String) -> Result<String, ApiError> {
+    pub fn get_session(&self, request_token: String) -> Result<Session, ApiError> {
         // Build params and signature
         let mut post_params: HashMap<&str, String> = HashMap::from([
             ("api_key", self.auth_config.api_key.clone()),
@@ -112,70 +431,164 @@ impl LastfmApi {
 
         // Make a request
         let url = format!("{}/2.0", self.api_host);
-        let response = self
-            .client
-            .post(url)
-            .form(&post_params)
-            .send()
-            .map_err(|e| ApiError::Generic(e.to_string()))?;
+        let response_text =
+            self.execute("auth.getSession", self.client.post(url).form(&post_params))?;
+        parse_session(&response_text)
+    }
 
-        let success = response.status().is_success();
-        let response_text = response.text().unwrap_or(String::new());
-        if !success {
-            error!("Error response to auth.getSession: {}", response_text);
-            return Err(ApiError::Generic("Unsuccessfull request".into()));
+    /// Get a session key with user credentials, for hosts without a browser
+    pub fn get_mobile_session(&self, username: &str, password: &str) -> Result<Session, ApiError> {
+        // Build params and signature
+        let mut post_params: HashMap<&str, String> = HashMap::from([
+            ("api_key", self.auth_config.api_key.clone()),
+            ("method", "auth.getMobileSession".to_string()),
+            ("username", username.to_string()),
+            ("password", password.to_string()),
+        ]);
+        let api_sig = self.compute_signature(&post_params);
+        post_params.insert("api_sig", api_sig);
+
+        // Make a request, the password is sent only in a POST body
+        let url = format!("{}/2.0", self.api_host);
+        let response_text = self.execute(
+            "auth.getMobileSession",
+            self.client.post(url).form(&post_params),
+        )?;
+        parse_session(&response_text)
+    }
+
+    pub fn scrobble(&self, scrobble: &Scrobble) -> Result<(), ApiError> {
+        // Build params and signature
+        let mut post_params: HashMap<&str, String> = HashMap::from([
+            ("api_key", self.auth_config.api_key.clone()),
+            ("method", "track.scrobble".to_string()),
+            ("sk", self.auth_config.session_key.clone()),
+        ]);
+        post_params.extend(scrobble.params());
+        let api_sig = self.compute_signature(&post_params);
+        post_params.insert("api_sig", api_sig);
+
+        // Make a request
+        let url = format!("{}/2.0", self.api_host);
+        let response_text =
+            self.execute("track.scrobble", self.client.post(url).form(&post_params))?;
+        match self.parse_scrobble_response(response_text)?.pop() {
+            Some(ScrobbleStatus::Accepted) => Ok(()),
+            Some(ScrobbleStatus::Ignored(reason)) => Err(ApiError::Unscrobbled(reason)),
+            None => Err(ApiError::Parse("Wrong response structure".into())),
         }
-        debug!("Response: {}", response_text);
-        let session_token: String = Element::parse(response_text.as_bytes())
-            .map_err(|e| ApiError::Parse(e.to_string()))?
-            .get_child("session")
-            .ok_or(ApiError::Parse("xml tag session".into()))?
-            .get_child("key")
-            .ok_or(ApiError::Parse("xml tag key".into()))?
-            .get_text()
-            .ok_or(ApiError::Parse("xml text".into()))?
-            .into_owned();
-        Ok(session_token)
     }
 
-    pub fn scrobble(
+    /// Notify Last.fm that a track has started playing
+    pub fn update_now_playing(
         &self,
         artist: String,
         track: String,
-        when: OffsetDateTime,
+        album: Option<String>,
+        duration: Option<i64>,
     ) -> Result<(), ApiError> {
         // Build params and signature
-        let timestamp_sec: i64 = when.unix_timestamp();
         let mut post_params: HashMap<&str, String> = HashMap::from([
             ("api_key", self.auth_config.api_key.clone()),
-            ("method", "track.scrobble".to_string()),
+            ("method", "track.updateNowPlaying".to_string()),
             ("artist", artist),
             ("track", track),
-            ("timestamp", timestamp_sec.to_string()),
             ("sk", self.auth_config.session_key.clone()),
         ]);
+        if let Some(album) = album {
+            post_params.insert("album", album);
+        }
+        if let Some(duration) = duration {
+            post_params.insert("duration", duration.to_string());
+        }
         let api_sig = self.compute_signature(&post_params);
         post_params.insert("api_sig", api_sig);
 
         // Make a request
         let url = format!("{}/2.0", self.api_host);
-        let response = self
-            .client
-            .post(url)
-            .form(&post_params)
-            .send()
-            .map_err(|e| ApiError::Generic(e.to_string()))?;
+        let response_text = self.execute(
+            "track.updateNowPlaying",
+            self.client.post(url).form(&post_params),
+        )?;
+        debug!("Now playing response: {}", response_text);
+        let elem_root =
+            Element::parse(response_text.as_bytes()).map_err(|e| ApiError::Parse(e.to_string()))?;
+        let elem_nowplaying = elem_root
+            .get_child("nowplaying")
+            .ok_or(ApiError::Parse("xml tag nowplaying".into()))?;
+        if let Some(elem_message) = elem_nowplaying.get_child("ignoredMessage") {
+            let reason_code = elem_message
+                .attributes
+                .get("code")
+                .map_or("0", |c| c.as_str());
+            if reason_code != "0" {
+                let reason_text = elem_message
+                    .get_text()
+                    .map_or(String::new(), |r| r.into_owned());
+                return Err(ApiError::Unscrobbled(format!(
+                    "{}: {}",
+                    reason_code, reason_text
+                )));
+            }
+        }
+        Ok(())
+    }
 
-        let success = response.status().is_success();
-        let response_text = response.text().unwrap_or(String::new());
-        if !success {
-            error!("Error response to track.scrobble: {}", response_text);
-            return Err(ApiError::Generic("Unsuccessfull request".into()));
+    /// Scrobble several tracks with as few requests as possible.
+    /// Stops at the first failed request, chunks sent before it stay accepted.
+    pub fn scrobble_batch(&self, scrobbles: &[Scrobble]) -> BatchOutcome {
+        let mut statuses = Vec::with_capacity(scrobbles.len());
+        for chunk in scrobbles.chunks(MAX_SCROBBLE_BATCH) {
+            match self.scrobble_chunk(chunk) {
+                Ok(chunk_statuses) => statuses.extend(chunk_statuses),
+                Err(e) => {
+                    return BatchOutcome {
+                        statuses,
+                        error: Some(e),
+                    }
+                }
+            }
+        }
+        BatchOutcome {
+            statuses,
+            error: None,
         }
-        self.parse_scrobble_response(response_text)
     }
 
-    fn parse_scrobble_response(&self, response_text: String) -> anyhow::Result<(), ApiError> {
+    fn scrobble_chunk(&self, scrobbles: &[Scrobble]) -> Result<Vec<ScrobbleStatus>, ApiError> {
+        // Build indexed params and signature
+        let mut post_params: HashMap<String, String> = HashMap::from([
+            ("api_key".to_string(), self.auth_config.api_key.clone()),
+            ("method".to_string(), "track.scrobble".to_string()),
+            ("sk".to_string(), self.auth_config.session_key.clone()),
+        ]);
+        for (idx, scrobble) in scrobbles.iter().enumerate() {
+            for (key, value) in scrobble.params() {
+                post_params.insert(format!("{}[{}]", key, idx), value);
+            }
+        }
+        let api_sig = self.compute_signature(&post_params);
+        post_params.insert("api_sig".to_string(), api_sig);
+
+        // Make a request
+        let url = format!("{}/2.0", self.api_host);
+        let response_text =
+            self.execute("track.scrobble", self.client.post(url).form(&post_params))?;
+        let statuses = self.parse_scrobble_response(response_text)?;
+        if statuses.len() != scrobbles.len() {
+            return Err(ApiError::Parse(format!(
+                "expected {} scrobbles in response, got {}",
+                scrobbles.len(),
+                statuses.len()
+            )));
+        }
+        Ok(statuses)
+    }
+
+    fn parse_scrobble_response(
+        &self,
+        response_text: String,
+    ) -> Result<Vec<ScrobbleStatus>, ApiError> {
         debug!("Scrobble response: {}", response_text);
         let elem_root =
             Element::parse(response_text.as_bytes()).map_err(|e| ApiError::Parse(e.to_string()))?;
@@ -183,38 +596,289 @@ impl LastfmApi {
             .get_child("scrobbles")
             .ok_or(ApiError::Parse("xml scrobbles key".into()))?;
 
-        let accepted_count: i64 = elem_scrobbles
+        let accepted_count: usize = elem_scrobbles
             .attributes
             .get("accepted")
             .ok_or(ApiError::Parse("no acccepted attr".into()))?
             .parse()
             .map_err(|_| ApiError::Parse("integer".into()))?;
-        let ignored_count: i64 = elem_scrobbles
+        let ignored_count: usize = elem_scrobbles
             .attributes
             .get("ignored")
             .ok_or(ApiError::Parse("no ignored attr".into()))?
             .parse()
             .map_err(|_| ApiError::Parse("integer".into()))?;
-        if accepted_count == 1 && ignored_count == 0 {
-            // It's ok
-            Ok(())
-        } else if accepted_count == 0 && ignored_count == 1 {
-            // Find a reason
-            let elem_message = elem_scrobbles
-                .get_child("scrobble")
-                .ok_or(ApiError::Parse("xml tag scrobble".into()))?
-                .get_child("ignoredMessage")
-                .ok_or(ApiError::Parse("xml tag ignoredMessage".into()))?;
-            let reason_code = elem_message.attributes.get("code").unwrap();
-            let reason_text = elem_message
-                .get_text()
-                .map_or(String::new(), |r| r.into_owned());
-            let reason = format!("{}: {}", reason_code, reason_text);
-            Err(ApiError::Unscrobbled(reason))
-        } else {
+
+        let statuses = elem_scrobbles
+            .children
+            .iter()
+            .filter_map(|node| node.as_element())
+            .filter(|elem| elem.name == "scrobble")
+            .map(|elem_scrobble| {
+                let elem_message = elem_scrobble
+                    .get_child("ignoredMessage")
+                    .ok_or(ApiError::Parse("xml tag ignoredMessage".into()))?;
+                let reason_code = elem_message
+                    .attributes
+                    .get("code")
+                    .map_or("0", |c| c.as_str());
+                if reason_code == "0" {
+                    Ok(ScrobbleStatus::Accepted)
+                } else {
+                    // Find a reason
+                    let reason_text = elem_message
+                        .get_text()
+                        .map_or(String::new(), |r| r.into_owned());
+                    Ok(ScrobbleStatus::Ignored(format!(
+                        "{}: {}",
+                        reason_code, reason_text
+                    )))
+                }
+            })
+            .collect::<Result<Vec<ScrobbleStatus>, ApiError>>()?;
+
+        let actual_accepted = statuses
+            .iter()
+            .filter(|status| **status == ScrobbleStatus::Accepted)
+            .count();
+        if actual_accepted != accepted_count || statuses.len() - actual_accepted != ignored_count {
             // Invalid structure
-            Err(ApiError::Parse("Wrong response structure".into()))
+            return Err(ApiError::Parse("Wrong response structure".into()));
         }
+        Ok(statuses)
+    }
+
+    /// Name of the user owning the session key
+    pub fn get_username(&self) -> Result<String, ApiError> {
+        // Build params and signature
+        let mut post_params: HashMap<&str, String> = HashMap::from([
+            ("api_key", self.auth_config.api_key.clone()),
+            ("method", "user.getInfo".to_string()),
+            ("sk", self.auth_config.session_key.clone()),
+        ]);
+        let api_sig = self.compute_signature(&post_params);
+        post_params.insert("api_sig", api_sig);
+        // Format is not a part of the signature
+        post_params.insert("format", "json".to_string());
+
+        // Make a request
+        let url = format!("{}/2.0", self.api_host);
+        let response_text =
+            self.execute("user.getInfo", self.client.post(url).form(&post_params))?;
+        let resp: Value = serde_json::from_str(&response_text).map_err(|_| ApiError::Json)?;
+        debug!("Resp {}", resp);
+        let username = resp
+            .get("user")
+            .and_then(|u| u.get("name"))
+            .and_then(|n| n.as_str())
+            .ok_or(ApiError::Json)?
+            .to_string();
+        Ok(username)
+    }
+
+    /// Canonical name of an artist if the given one is misspelled
+    pub fn get_artist_correction(&self, artist: &str) -> Result<Option<String>, ApiError> {
+        let url = format!(
+            "{api_host}/2.0/?method=artist.getCorrection&artist={artist}&api_key={key}&format=json",
+            api_host = self.api_host,
+            artist = urlencoding::encode(artist),
+            key = self.auth_config.api_key
+        );
+        let response_text = self.execute("artist.getCorrection", self.client.post(url).body(""))?;
+        let resp: Value = serde_json::from_str(&response_text).map_err(|_| ApiError::Json)?;
+        debug!("Resp {}", resp);
+
+        let Some(jcorrection) = parse_correction(&resp) else {
+            return Ok(None);
+        };
+        let name = jcorrection
+            .get("artist")
+            .and_then(|a| a.get("name"))
+            .and_then(|n| n.as_str())
+            .ok_or(ApiError::Json)?;
+        Ok(Some(name.to_string()).filter(|name| name != artist))
+    }
+
+    /// Canonical artist and track names if the given ones are misspelled
+    pub fn get_track_correction(
+        &self,
+        artist: &str,
+        track: &str,
+    ) -> Result<Option<(String, String)>, ApiError> {
+        let url = format!(
+            "{api_host}/2.0/\
+                ?method=track.getCorrection&artist={artist}&track={track}&api_key={key}&format=json",
+            api_host = self.api_host,
+            artist = urlencoding::encode(artist),
+            track = urlencoding::encode(track),
+            key = self.auth_config.api_key
+        );
+        let response_text = self.execute("track.getCorrection", self.client.post(url).body(""))?;
+        let resp: Value = serde_json::from_str(&response_text).map_err(|_| ApiError::Json)?;
+        debug!("Resp {}", resp);
+
+        let Some(jtrack) = parse_correction(&resp).and_then(|c| c.get("track")) else {
+            return Ok(None);
+        };
+        let name = jtrack
+            .get("name")
+            .and_then(|n| n.as_str())
+            .ok_or(ApiError::Json)?;
+        let artist_name = jtrack
+            .get("artist")
+            .and_then(|a| a.get("name"))
+            .and_then(|n| n.as_str())
+            .ok_or(ApiError::Json)?;
+        if artist_name == artist && name == track {
+            return Ok(None);
+        }
+        Ok(Some((artist_name.to_string(), name.to_string())))
+    }
+
+    /// Duration and mbid of a track
+    pub fn get_track_info(&self, artist: &str, track: &str) -> Result<TrackInfo, ApiError> {
+        let url = format!(
+            "{api_host}/2.0/\
+                ?method=track.getInfo&artist={artist}&track={track}&api_key={key}&format=json",
+            api_host = self.api_host,
+            artist = urlencoding::encode(artist),
+            track = urlencoding::encode(track),
+            key = self.auth_config.api_key
+        );
+        let response_text = self.execute("track.getInfo", self.client.post(url).body(""))?;
+        let resp: Value = serde_json::from_str(&response_text).map_err(|_| ApiError::Json)?;
+        debug!("Resp {}", resp);
+
+        let jtrack = resp.get("track").ok_or(ApiError::Json)?;
+        // Duration is in milliseconds, as a number or a numeric string
+        let duration = jtrack
+            .get("duration")
+            .and_then(|duration| match duration {
+                Value::Number(n) => n.as_i64(),
+                Value::String(s) => s.parse().ok(),
+                _ => None,
+            })
+            .filter(|&duration| duration > 0)
+            .map(|duration| (duration + 500) / 1000);
+        Ok(TrackInfo {
+            duration,
+            mbid: parse_mbid(jtrack),
+        })
+    }
+
+    /// Albums with names similar to the given one, best matches first
+    pub fn search_album(&self, album: &str) -> Result<Vec<AlbumMatch>, ApiError> {
+        let url = format!(
+            "{api_host}/2.0/?method=album.search&album={album}&limit=30&api_key={key}&format=json",
+            api_host = self.api_host,
+            album = urlencoding::encode(album),
+            key = self.auth_config.api_key
+        );
+        let response_text = self.execute("album.search", self.client.post(url).body(""))?;
+        let resp: Value = serde_json::from_str(&response_text).map_err(|_| ApiError::Json)?;
+        debug!("Resp {}", resp);
+
+        let jmatches = resp
+            .get("results")
+            .and_then(|r| r.get("albummatches"))
+            .and_then(|m| m.get("album"))
+            .ok_or(ApiError::Json)?;
+        // A single album comes as an object instead of an array
+        let jalbums = match jmatches {
+            Value::Array(jalbums) => jalbums.clone(),
+            jalbum @ Value::Object(_) => vec![jalbum.clone()],
+            _ => Vec::new(),
+        };
+        jalbums
+            .iter()
+            .map(|jalbum| {
+                let field = |name: &str| {
+                    jalbum
+                        .get(name)
+                        .and_then(|v| v.as_str())
+                        .map(|v| v.to_string())
+                        .ok_or(ApiError::Json)
+                };
+                Ok(AlbumMatch {
+                    title: field("name")?,
+                    artist: field("artist")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Scrobbles of a user within a time range, newest first
+    pub fn get_recent_tracks(
+        &self,
+        user: &str,
+        from: OffsetDateTime,
+        to: OffsetDateTime,
+    ) -> Result<Vec<Scrobble>, ApiError> {
+        let mut scrobbles = Vec::new();
+        let mut page = 1;
+        loop {
+            let url = format!(
+                "{api_host}/2.0/\
+                    ?method=user.getRecentTracks&user={user}&from={from}&to={to}\
+                    &limit=200&page={page}&api_key={key}&format=json",
+                api_host = self.api_host,
+                user = urlencoding::encode(user),
+                from = from.unix_timestamp(),
+                to = to.unix_timestamp(),
+                page = page,
+                key = self.auth_config.api_key
+            );
+            let response_text =
+                self.execute("user.getRecentTracks", self.client.post(url).body(""))?;
+            let resp: Value = serde_json::from_str(&response_text).map_err(|_| ApiError::Json)?;
+            debug!("Resp {}", resp);
+
+            let jrecent = resp.get("recenttracks").ok_or(ApiError::Json)?;
+            // A single track comes as an object instead of an array
+            let jtracks = match jrecent.get("track") {
+                Some(Value::Array(jtracks)) => jtracks.clone(),
+                Some(jtrack @ Value::Object(_)) => vec![jtrack.clone()],
+                _ => Vec::new(),
+            };
+            for jtrack in &jtracks {
+                // Now playing track has no date
+                let Some(uts) = jtrack
+                    .get("date")
+                    .and_then(|d| d.get("uts"))
+                    .and_then(|u| u.as_str())
+                else {
+                    continue;
+                };
+                let when = uts
+                    .parse::<i64>()
+                    .ok()
+                    .and_then(|uts| OffsetDateTime::from_unix_timestamp(uts).ok())
+                    .ok_or(ApiError::Parse("recent track date".into()))?;
+                let artist = jtrack
+                    .get("artist")
+                    .and_then(|a| a.get("#text"))
+                    .and_then(|a| a.as_str())
+                    .ok_or(ApiError::Json)?;
+                let title = jtrack
+                    .get("name")
+                    .and_then(|n| n.as_str())
+                    .ok_or(ApiError::Json)?;
+                scrobbles.push(Scrobble::new(artist.to_string(), title.to_string(), when));
+            }
+
+            let total_pages: u32 = jrecent
+                .get("@attr")
+                .and_then(|a| a.get("totalPages"))
+                .and_then(|p| p.as_str())
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(1);
+            if page >= total_pages {
+                break;
+            }
+            page += 1;
+        }
+        debug!("Found {} recent tracks", scrobbles.len());
+        Ok(scrobbles)
     }
 
     pub fn get_album_tracks(&self, artist: String, album: String) -> Result<Album, ApiError> {
@@ -226,18 +890,8 @@ impl LastfmApi {
             album = urlencoding::encode(&album),
             key = self.auth_config.api_key
         );
-        let response = self
-            .client
-            .post(url)
-            .body("")
-            .send()
-            .map_err(|e| ApiError::Generic(e.to_string()))?;
-
-        if !response.status().is_success() {
-            error!("Response: {}", response.text().unwrap_or("".to_string()));
-            return Err(ApiError::Generic("Unsuccessfull request".into()));
-        }
-        let resp: serde_json::Value = response.json().unwrap();
+        let response_text = self.execute("album.getInfo", self.client.post(url).body(""))?;
+        let resp: Value = serde_json::from_str(&response_text).map_err(|_| ApiError::Json)?;
         debug!("Resp {}", resp);
 
         let jalbum = resp
@@ -260,10 +914,17 @@ impl LastfmApi {
 
         debug!("Found {} tracks", jtracks.len());
 
-        let tracks: Vec<Track> = jtracks
+        let album_artist = jalbum
+            .get("artist")
+            .and_then(|v| v.as_str())
+            .unwrap_or(artist.as_str())
+            .to_string();
+
+        let mut tracks: Vec<Track> = jtracks
             .iter()
-            .map(|jtrack| self.parse_track(jtrack))
+            .map(|jtrack| self.parse_track(jtrack, &album_artist))
             .collect::<Result<Vec<Track>, ApiError>>()?;
+        assign_discs(&mut tracks);
 
         let title = resp
             .as_object()
@@ -289,35 +950,137 @@ impl LastfmApi {
             .map(|s| s.to_string());
 
         let album_struct = Album {
-            title,
+            title: Some(title),
+            artist: album_artist,
             tracks,
             url: album_url,
+            mbid: parse_mbid(jalbum),
         };
 
         Ok(album_struct)
     }
 
-    fn parse_track(&self, jtrack: &Value) -> anyhow::Result<Track, ApiError> {
-        let default_duration: i64 = 300;
+    fn parse_track(&self, jtrack: &Value, album_artist: &str) -> anyhow::Result<Track, ApiError> {
         let title = jtrack
             .get("name")
             .ok_or(ApiError::Json)?
             .as_str()
             .ok_or(ApiError::Json)?
             .to_string();
+        // Duration is null or zero when Last.fm does not know it
         let duration = jtrack
             .get("duration")
             .ok_or(ApiError::Json)?
             .as_i64()
-            .unwrap_or(default_duration);
-        Ok(Track { duration, title })
+            .filter(|&duration| duration > 0);
+        // Rank is a number or a numeric string depending on the release
+        let number = jtrack
+            .get("@attr")
+            .and_then(|attr| attr.get("rank"))
+            .and_then(|rank| match rank {
+                Value::Number(n) => n.as_u64().map(|n| n as u32),
+                Value::String(s) => s.parse().ok(),
+                _ => None,
+            });
+        // Compilations list a real artist of every track
+        let artist = jtrack
+            .get("artist")
+            .and_then(|jartist| jartist.get("name").or(Some(jartist)))
+            .and_then(|name| name.as_str())
+            .filter(|name| !name.is_empty())
+            .map(|name| name.to_string());
+        Ok(Track {
+            duration: duration.unwrap_or(DEFAULT_TRACK_DURATION),
+            duration_source: if duration.is_some() {
+                DurationSource::Release
+            } else {
+                DurationSource::Default
+            },
+            title,
+            artist,
+            album_artist: Some(album_artist.to_string()),
+            number,
+            disc: None,
+            mbid: parse_mbid(jtrack),
+        })
+    }
+}
+
+/// Split album tracks into discs. Last.fm restarts ranks on every disc
+/// and some releases mark discs in track names like "Song (Disc 2)".
+fn assign_discs(tracks: &mut [Track]) {
+    let mut disc = 1;
+    let mut prev_rank: Option<u32> = None;
+    for track in tracks.iter_mut() {
+        if let (Some(prev), Some(rank)) = (prev_rank, track.number) {
+            if rank <= prev {
+                disc += 1;
+            }
+        }
+        prev_rank = track.number.or(prev_rank);
+        track.disc = Some(disc_from_name(&track.title).unwrap_or(disc));
     }
 }
 
+/// Find a disc marker like "Disc 2" or "CD2" in a track name
+fn disc_from_name(name: &str) -> Option<u32> {
+    let name = name.to_lowercase();
+    let words: Vec<&str> = name
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+    words
+        .iter()
+        .enumerate()
+        .find_map(|(idx, word)| match *word {
+            "disc" | "disk" | "cd" => words.get(idx + 1)?.parse().ok(),
+            _ => word.strip_prefix("cd")?.parse().ok(),
+        })
+}
+
+/// The first correction of getCorrection response, corrections are a blank string if none found
+fn parse_correction(resp: &Value) -> Option<&Value> {
+    match resp.get("corrections")?.get("correction")? {
+        Value::Array(jcorrections) => jcorrections.first(),
+        jcorrection => Some(jcorrection),
+    }
+}
+
+/// Extract a session from auth.getSession or auth.getMobileSession XML response
+fn parse_session(response_text: &str) -> Result<Session, ApiError> {
+    let root =
+        Element::parse(response_text.as_bytes()).map_err(|e| ApiError::Parse(e.to_string()))?;
+    let session = root
+        .get_child("session")
+        .ok_or(ApiError::Parse("xml tag session".into()))?;
+    let text = |tag: &str| {
+        session
+            .get_child(tag)
+            .ok_or(ApiError::Parse(format!("xml tag {}", tag)))?
+            .get_text()
+            .map(|text| text.into_owned())
+            .ok_or(ApiError::Parse("xml text".into()))
+    };
+    Ok(Session {
+        name: text("name")?,
+        key: text("key")?,
+    })
+}
+
+/// Extract a non-empty mbid from a JSON object
+fn parse_mbid(jobject: &Value) -> Option<String> {
+    jobject
+        .get("mbid")
+        .and_then(|v| v.as_str())
+        .filter(|mbid| !mbid.is_empty())
+        .map(|mbid| mbid.to_string())
+}
+
 /// Last.fm API client builder
 pub struct LastfmApiBuilder {
     auth_config: AuthConfig,
     api_host: String,
+    retry_policy: RetryPolicy,
 }
 
 #[allow(dead_code)]
@@ -326,6 +1089,7 @@ impl LastfmApiBuilder {
         LastfmApiBuilder {
             auth_config,
             api_host: AUDIOSCROBBLER_HOST.to_string(),
+            retry_policy: RetryPolicy::default(),
         }
     }
 
@@ -334,8 +1098,13 @@ impl LastfmApiBuilder {
         self
     }
 
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> LastfmApiBuilder {
+        self.retry_policy = retry_policy;
+        self
+    }
+
     pub fn build(self) -> LastfmApi {
-        LastfmApi::new(self.auth_config, self.api_host)
+        LastfmApi::new(self.auth_config, self.api_host, self.retry_policy)
     }
 }
 
@@ -419,12 +1188,53 @@ mod tests {
         mock_gettoken.assert();
         assert!(res.is_ok());
         let album = res.unwrap();
-        assert_eq!(album.title, "A New Stereophonic Sound Spectacular");
+        assert_eq!(
+            album.title.as_deref(),
+            Some("A New Stereophonic Sound Spectacular")
+        );
         assert_eq!(
             album.url.unwrap_or("".into()),
             "https://www.last.fm/music/Hooverphonic/A+New+Stereophonic+Sound+Spectacular"
         );
+        assert_eq!(album.artist, "Hooverphonic");
+        assert_eq!(
+            album.mbid.as_deref(),
+            Some("4e9ddd61-dc7f-41a2-acb2-a78d91031eb7")
+        );
         assert_eq!(album.tracks.len(), 11);
+        assert_eq!(album.tracks[0].number, Some(1));
+        assert_eq!(album.tracks[0].artist.as_deref(), Some("Hooverphonic"));
+        assert_eq!(album.tracks[2].artist.as_deref(), Some("Hoover"));
+        assert_eq!(
+            album.tracks[2].album_artist.as_deref(),
+            Some("Hooverphonic")
+        );
+        assert_eq!(album.tracks[10].number, Some(11));
+    }
+
+    #[test]
+    fn test_assign_discs() {
+        let track = |title: &str, number| Track {
+            title: title.to_string(),
+            artist: None,
+            album_artist: None,
+            duration: 200,
+            duration_source: DurationSource::Release,
+            number: Some(number),
+            disc: None,
+            mbid: None,
+        };
+        let mut tracks = vec![
+            track("Eden", 1),
+            track("One", 2),
+            track("Eden (Live)", 1),
+            track("Battersea [CD3]", 2),
+        ];
+        assign_discs(&mut tracks);
+        let discs: Vec<Option<u32>> = tracks.iter().map(|t| t.disc).collect();
+        assert_eq!(discs, vec![Some(1), Some(1), Some(2), Some(3)]);
+        assert_eq!(disc_from_name("Intro - Disc 2"), Some(2));
+        assert_eq!(disc_from_name("Discotheque"), None);
     }
 
     #[test]
@@ -435,14 +1245,446 @@ mod tests {
         let mock_gettoken = server.mock(|when, then| {
             when.method(POST)
                 .path("/2.0")
-                .x_www_form_urlencoded_tuple("method", "track.scrobble");
+                .x_www_form_urlencoded_tuple("method", "track.scrobble")
+                .x_www_form_urlencoded_tuple("track", "Eden")
+                .x_www_form_urlencoded_tuple("album", "Blue Wonder Power Milk")
+                .x_www_form_urlencoded_tuple("trackNumber", "3");
             then.status(200)
                 .header("content-type", "application/json")
                 .body(response_text);
         });
 
-        let res = mock_client(&server).scrobble("Hooverphonic".into(), "Eden".into(), now_local());
+        let res = mock_client(&server).scrobble(&Scrobble {
+            album: Some("Blue Wonder Power Milk".into()),
+            track_number: Some(3),
+            ..Scrobble::new("Hooverphonic".into(), "Eden".into(), now_local())
+        });
         mock_gettoken.assert();
         assert!(res.is_ok());
     }
+
+    #[test]
+    fn test_scrobble_batch() {
+        let server = MockServer::start();
+
+        let response_text = include_str!("data/resp.scrobble_batch.xml");
+        let mock_scrobble = server.mock(|when, then| {
+            when.method(POST)
+                .path("/2.0")
+                .x_www_form_urlencoded_tuple("method", "track.scrobble")
+                .x_www_form_urlencoded_tuple("artist[0]", "Hooverphonic")
+                .x_www_form_urlencoded_tuple("track[2]", "Eden")
+                .x_www_form_urlencoded_tuple("album[1]", "A New Stereophonic Sound Spectacular");
+            then.status(200)
+                .header("content-type", "application/xml")
+                .body(response_text);
+        });
+
+        let now = now_local();
+        let scrobbles: Vec<Scrobble> = ["Inhaler", "Cinderella", "Eden"]
+            .iter()
+            .map(|title| Scrobble {
+                album: Some("A New Stereophonic Sound Spectacular".into()),
+                ..Scrobble::new("Hooverphonic".into(), title.to_string(), now)
+            })
+            .collect();
+        let res = mock_client(&server).scrobble_batch(&scrobbles);
+        mock_scrobble.assert();
+        assert!(res.error.is_none());
+        assert_eq!(
+            res.statuses,
+            vec![
+                ScrobbleStatus::Accepted,
+                ScrobbleStatus::Accepted,
+                ScrobbleStatus::Ignored("3: Timestamp too old".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scrobble_batch_partial() {
+        let server = MockServer::start();
+
+        let accepted: String = (0..MAX_SCROBBLE_BATCH)
+            .map(|_| "<scrobble><ignoredMessage code=\"0\"></ignoredMessage></scrobble>")
+            .collect();
+        let mock_first = server.mock(|when, then| {
+            when.method(POST)
+                .path("/2.0")
+                .x_www_form_urlencoded_tuple("track[0]", "Track 1");
+            then.status(200)
+                .header("content-type", "application/xml")
+                .body(format!(
+                    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<lfm status=\"ok\">\
+                    <scrobbles accepted=\"{}\" ignored=\"0\">{}</scrobbles></lfm>",
+                    MAX_SCROBBLE_BATCH, accepted
+                ));
+        });
+        let mock_second = server.mock(|when, then| {
+            when.method(POST)
+                .path("/2.0")
+                .x_www_form_urlencoded_tuple("track[0]", "Track 51");
+            then.status(503)
+                .header("content-type", "application/xml")
+                .body(
+                    r#"<?xml version="1.0" encoding="UTF-8"?>
+                    <lfm status="failed"><error code="11">Service Offline</error></lfm>"#,
+                );
+        });
+
+        let api = LastfmApiBuilder::new(AuthConfig {
+            api_key: String::new(),
+            secret_key: String::new(),
+            session_key: String::new(),
+        })
+        .with_api_host(format!("http://{}", server.address()))
+        .with_retry_policy(RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        })
+        .build();
+        let now = now_local();
+        let scrobbles: Vec<Scrobble> = (1..=60)
+            .map(|idx| Scrobble::new("Hooverphonic".into(), format!("Track {}", idx), now))
+            .collect();
+        let res = api.scrobble_batch(&scrobbles);
+        mock_first.assert();
+        mock_second.assert();
+        assert_eq!(res.sent(), MAX_SCROBBLE_BATCH);
+        assert!(matches!(res.error, Some(ApiError::ServiceOffline(_))));
+    }
+
+    #[test]
+    fn test_parse_error_response() {
+        let res = parse_error_response(r#"{"error": 29, "message": "Rate limit exceeded"}"#);
+        assert!(matches!(res, Some(ApiError::RateLimit(_))));
+        assert!(res.unwrap().is_retryable());
+
+        let res = parse_error_response(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <lfm status="failed"><error code="9">Invalid session key</error></lfm>"#,
+        );
+        assert!(matches!(res, Some(ApiError::InvalidSession(ref m)) if m == "Invalid session key"));
+        assert!(!res.unwrap().is_retryable());
+
+        assert!(parse_error_response(r#"{"token": "secrettoken"}"#).is_none());
+        assert!(parse_error_response(include_str!("data/resp.scrobble.json")).is_none());
+    }
+
+    #[test]
+    fn test_scrobble_invalid_session() {
+        let server = MockServer::start();
+
+        let mock_scrobble = server.mock(|when, then| {
+            when.method(POST)
+                .path("/2.0")
+                .x_www_form_urlencoded_tuple("method", "track.scrobble");
+            then.status(403)
+                .header("content-type", "application/xml")
+                .body(
+                    r#"<?xml version="1.0" encoding="UTF-8"?>
+                    <lfm status="failed"><error code="9">Invalid session key</error></lfm>"#,
+                );
+        });
+
+        let res = mock_client(&server).scrobble(&Scrobble::new(
+            "Hooverphonic".into(),
+            "Eden".into(),
+            now_local(),
+        ));
+        mock_scrobble.assert();
+        assert!(matches!(res, Err(ApiError::InvalidSession(_))));
+    }
+
+    #[test]
+    fn test_retry_temporary_error() {
+        let server = MockServer::start();
+
+        let mock_album = server.mock(|when, then| {
+            when.method(POST)
+                .path("/2.0/")
+                .query_param("method", "album.getInfo");
+            then.status(503)
+                .header("content-type", "application/json")
+                .body(r#"{"error": 16, "message": "There was a temporary error processing your request"}"#);
+        });
+
+        let api_host = "http://".to_owned() + &server.address().to_string();
+        let auth_config = AuthConfig {
+            api_key: String::new(),
+            secret_key: String::new(),
+            session_key: String::new(),
+        };
+        let api = LastfmApiBuilder::new(auth_config)
+            .with_api_host(api_host)
+            .with_retry_policy(RetryPolicy {
+                max_attempts: 3,
+                initial_delay: std::time::Duration::ZERO,
+                max_delay: std::time::Duration::ZERO,
+            })
+            .build();
+
+        let res = api.get_album_tracks("Hooverphonic".into(), "Eden".into());
+        mock_album.assert_hits(3);
+        assert!(matches!(res, Err(ApiError::TemporarilyUnavailable(_))));
+    }
+
+    #[test]
+    fn test_retry_server_error() {
+        let server = MockServer::start();
+
+        // A gateway page instead of a Last.fm error
+        let mock_album = server.mock(|when, then| {
+            when.method(POST)
+                .path("/2.0/")
+                .query_param("method", "album.getInfo");
+            then.status(503)
+                .header("content-type", "text/html")
+                .body("<html><body><h1>503 Service Unavailable</h1></body></html>");
+        });
+
+        let api = LastfmApiBuilder::new(AuthConfig {
+            api_key: String::new(),
+            secret_key: String::new(),
+            session_key: String::new(),
+        })
+        .with_api_host(format!("http://{}", server.address()))
+        .with_retry_policy(RetryPolicy {
+            max_attempts: 2,
+            initial_delay: std::time::Duration::ZERO,
+            max_delay: std::time::Duration::ZERO,
+        })
+        .build();
+
+        let res = api.get_album_tracks("Hooverphonic".into(), "Eden".into());
+        mock_album.assert_hits(2);
+        assert!(matches!(&res, Err(ApiError::Server(_))));
+        assert!(res.unwrap_err().is_retryable());
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let policy = RetryPolicy::default();
+        let err = ApiError::Network("timeout".into());
+        let first = policy.delay(1, &err);
+        assert!(first >= std::time::Duration::from_millis(500));
+        assert!(first <= std::time::Duration::from_secs(1));
+        assert!(policy.delay(10, &err) <= policy.max_delay);
+        assert!(policy.delay(2, &ApiError::RateLimit(String::new())) >= policy.delay(1, &err));
+    }
+
+    #[test]
+    fn test_update_now_playing() {
+        let server = MockServer::start();
+
+        let response_text = include_str!("data/resp.nowplaying.xml");
+        let mock_nowplaying = server.mock(|when, then| {
+            when.method(POST)
+                .path("/2.0")
+                .x_www_form_urlencoded_tuple("method", "track.updateNowPlaying")
+                .x_www_form_urlencoded_tuple("artist", "Hooverphonic")
+                .x_www_form_urlencoded_tuple("duration", "240");
+            then.status(200)
+                .header("content-type", "application/xml")
+                .body(response_text);
+        });
+
+        let res = mock_client(&server).update_now_playing(
+            "Hooverphonic".into(),
+            "Eden".into(),
+            None,
+            Some(240),
+        );
+        mock_nowplaying.assert();
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn test_get_recent_tracks() {
+        let server = MockServer::start();
+
+        let response_text = include_str!("data/resp.recenttracks.json");
+        let mock_recent = server.mock(|when, then| {
+            when.method(POST)
+                .path("/2.0/")
+                .query_param("method", "user.getRecentTracks")
+                .query_param("user", "rj")
+                .query_param("from", "1689470000");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(response_text);
+        });
+
+        let res = mock_client(&server).get_recent_tracks(
+            "rj",
+            OffsetDateTime::from_unix_timestamp(1689470000).unwrap(),
+            OffsetDateTime::from_unix_timestamp(1689480000).unwrap(),
+        );
+        mock_recent.assert();
+        let recent = res.unwrap();
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].artist, "Hooverphonic");
+        assert_eq!(recent[0].track, "Eden");
+        assert_eq!(recent[0].when.unix_timestamp(), 1689478983);
+    }
+
+    #[test]
+    fn test_search_album() {
+        let server = MockServer::start();
+
+        let response_text = include_str!("data/resp.albumsearch.json");
+        let mock_search = server.mock(|when, then| {
+            when.method(POST)
+                .path("/2.0/")
+                .query_param("method", "album.search")
+                .query_param("album", "Blue Wonder");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(response_text);
+        });
+
+        let res = mock_client(&server).search_album("Blue Wonder");
+        mock_search.assert();
+        let matches = res.unwrap();
+        assert_eq!(matches.len(), 3);
+        assert_eq!(matches[0].title, "Blue Wonder Power Milk");
+        assert_eq!(matches[0].artist, "Hooverphonic");
+        assert_eq!(matches[2].artist, "Blue Wonder");
+    }
+
+    #[test]
+    fn test_get_corrections() {
+        let server = MockServer::start();
+
+        let mock_artist = server.mock(|when, then| {
+            when.method(POST)
+                .path("/2.0/")
+                .query_param("method", "artist.getCorrection")
+                .query_param("artist", "Guns and Roses");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(include_str!("data/resp.artistcorrection.json"));
+        });
+        let mock_no_correction = server.mock(|when, then| {
+            when.method(POST)
+                .path("/2.0/")
+                .query_param("method", "artist.getCorrection")
+                .query_param("artist", "Hooverphonic");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body("{\"corrections\": \"\\n            \"}");
+        });
+        let mock_track = server.mock(|when, then| {
+            when.method(POST)
+                .path("/2.0/")
+                .query_param("method", "track.getCorrection")
+                .query_param("artist", "Simon and Garfunkel");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(include_str!("data/resp.trackcorrection.json"));
+        });
+
+        let client = mock_client(&server);
+        assert_eq!(
+            client.get_artist_correction("Guns and Roses").unwrap(),
+            Some("Guns N' Roses".to_string())
+        );
+        assert_eq!(client.get_artist_correction("Hooverphonic").unwrap(), None);
+        assert_eq!(
+            client
+                .get_track_correction("Simon and Garfunkel", "Mrs. Robinson")
+                .unwrap(),
+            Some(("Simon & Garfunkel".to_string(), "Mrs. Robinson".to_string()))
+        );
+        mock_artist.assert();
+        mock_no_correction.assert();
+        mock_track.assert();
+    }
+
+    #[test]
+    fn test_get_track_info() {
+        let server = MockServer::start();
+
+        let mock_info = server.mock(|when, then| {
+            when.method(POST)
+                .path("/2.0/")
+                .query_param("method", "track.getInfo")
+                .query_param("artist", "Hooverphonic")
+                .query_param("track", "Eden");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(include_str!("data/resp.trackinfo.json"));
+        });
+
+        let info = mock_client(&server)
+            .get_track_info("Hooverphonic", "Eden")
+            .unwrap();
+        mock_info.assert();
+        assert_eq!(info.duration, Some(241));
+        assert_eq!(
+            info.mbid.as_deref(),
+            Some("7cbdba3e-2b7d-4f85-a05a-5d4bf1b0ab67")
+        );
+    }
+
+    #[test]
+    fn test_get_mobile_session() {
+        let server = MockServer::start();
+
+        let mock_session = server.mock(|when, then| {
+            when.method(POST)
+                .path("/2.0")
+                .x_www_form_urlencoded_tuple("method", "auth.getMobileSession")
+                .x_www_form_urlencoded_tuple("username", "rj")
+                .x_www_form_urlencoded_tuple("password", "p@ss word")
+                .x_www_form_urlencoded_key_exists("api_sig");
+            then.status(200).body(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+                <lfm status=\"ok\"><session><name>rj</name>\
+                <key>d580d57f32848f5dcf574d1ce18d78b2</key>\
+                <subscriber>0</subscriber></session></lfm>",
+            );
+        });
+        let mock_failed = server.mock(|when, then| {
+            when.method(POST)
+                .path("/2.0")
+                .x_www_form_urlencoded_tuple("method", "auth.getMobileSession")
+                .x_www_form_urlencoded_tuple("password", "wrong");
+            then.status(403).body(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+                <lfm status=\"failed\"><error code=\"4\">\
+                Authentication Failed - You do not have permissions to access the service\
+                </error></lfm>",
+            );
+        });
+
+        let client = mock_client(&server);
+        let session = client.get_mobile_session("rj", "p@ss word").unwrap();
+        assert_eq!(session.name, "rj");
+        assert_eq!(session.key, "d580d57f32848f5dcf574d1ce18d78b2");
+        assert!(matches!(
+            client.get_mobile_session("rj", "wrong"),
+            Err(ApiError::AuthenticationFailed(_))
+        ));
+        mock_session.assert();
+        mock_failed.assert();
+    }
+
+    #[test]
+    fn test_get_username() {
+        let server = MockServer::start();
+
+        let mock_userinfo = server.mock(|when, then| {
+            when.method(POST)
+                .path("/2.0")
+                .x_www_form_urlencoded_tuple("method", "user.getInfo");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(r#"{"user": {"name": "rj", "playcount": "1000"}}"#);
+        });
+
+        let res = mock_client(&server).get_username();
+        mock_userinfo.assert();
+        assert_eq!(res.unwrap(), "rj");
+    }
 }
@@ -1,4 +1,16 @@
+pub mod audiofiles;
 pub mod auth;
+pub mod credentials;
+pub mod cue;
+pub mod import;
 pub mod lastfmapi;
+pub mod limits;
+pub mod musicbrainz;
+pub mod playlist;
+pub mod prompt;
+pub mod queue;
 pub mod scrobbler;
+pub mod scrobblerlog;
+pub mod selection;
 pub mod utils;
+pub mod webauth;
@@ -0,0 +1,176 @@
+use clap::ValueEnum;
+use time::{Duration, OffsetDateTime};
+
+/// Last.fm ignores scrobbles older than this
+pub const MAX_SCROBBLE_AGE: Duration = Duration::days(14);
+
+/// Keep fixed timestamps a bit away from the limits
+const SAFETY_MARGIN: Duration = Duration::minutes(1);
+
+/// What to do with timestamps outside of Last.fm limits
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum LimitPolicy {
+    /// Do not scrobble anything
+    #[default]
+    Refuse,
+    /// Move offending timestamps to the nearest allowed moment keeping their spacing
+    Clamp,
+    /// Move the whole timeline to fit into the allowed window
+    Shift,
+}
+
+/// Why Last.fm would ignore a timestamp
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum LimitViolation {
+    #[error("is older than 14 days")]
+    TooOld,
+    #[error("is in the future")]
+    InFuture,
+}
+
+/// Check a timestamp against Last.fm limits
+pub fn check_timestamp(when: OffsetDateTime, now: OffsetDateTime) -> Option<LimitViolation> {
+    if when < now - MAX_SCROBBLE_AGE {
+        Some(LimitViolation::TooOld)
+    } else if when > now {
+        Some(LimitViolation::InFuture)
+    } else {
+        None
+    }
+}
+
+/// Fit timestamps into Last.fm limits according to the policy
+pub fn fit_timeline(
+    times: &[OffsetDateTime],
+    now: OffsetDateTime,
+    policy: LimitPolicy,
+) -> anyhow::Result<Vec<OffsetDateTime>> {
+    let oldest = now - MAX_SCROBBLE_AGE + SAFETY_MARGIN;
+    let newest = now;
+    let violations = times
+        .iter()
+        .filter(|&&when| check_timestamp(when, now).is_some())
+        .count();
+    if violations == 0 {
+        return Ok(times.to_vec());
+    }
+    match policy {
+        LimitPolicy::Refuse => anyhow::bail!(
+            "{} timestamps are outside of Last.fm limits, \
+            use --out-of-range clamp or --out-of-range shift to fix them",
+            violations
+        ),
+        LimitPolicy::Clamp => {
+            // Equal timestamps would be duplicates, so offending ones are moved together
+            let old_shift = times
+                .iter()
+                .filter(|&&when| check_timestamp(when, now) == Some(LimitViolation::TooOld))
+                .min()
+                .map(|&first| oldest - first);
+            let future_shift = times
+                .iter()
+                .filter(|&&when| check_timestamp(when, now) == Some(LimitViolation::InFuture))
+                .max()
+                .map(|&last| newest - last);
+            Ok(times
+                .iter()
+                .map(|&when| match check_timestamp(when, now) {
+                    Some(LimitViolation::TooOld) => {
+                        old_shift.map_or(when, |shift| (when + shift).min(newest))
+                    }
+                    Some(LimitViolation::InFuture) => {
+                        future_shift.map_or(when, |shift| (when + shift).max(oldest))
+                    }
+                    None => when,
+                })
+                .collect())
+        }
+        LimitPolicy::Shift => {
+            let (Some(&first), Some(&last)) = (times.iter().min(), times.iter().max()) else {
+                return Ok(Vec::new());
+            };
+            if last - first > newest - oldest {
+                anyhow::bail!("Timeline is longer than 14 days and cannot be shifted");
+            }
+            let shift = if last > newest {
+                newest - last
+            } else {
+                oldest - first
+            };
+            Ok(times.iter().map(|&when| when + shift).collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use test_log::test;
+    use time::macros::datetime;
+
+    #[test]
+    fn test_check_timestamp() {
+        let now = datetime!(2026-10-16 12:00 UTC);
+        assert_eq!(check_timestamp(now - Duration::hours(1), now), None);
+        assert_eq!(
+            check_timestamp(now - Duration::days(15), now),
+            Some(LimitViolation::TooOld)
+        );
+        assert_eq!(
+            check_timestamp(now + Duration::minutes(1), now),
+            Some(LimitViolation::InFuture)
+        );
+    }
+
+    #[test]
+    fn test_fit_timeline() {
+        let now = datetime!(2026-10-16 12:00 UTC);
+        let times = vec![now - Duration::minutes(5), now + Duration::minutes(5)];
+
+        assert!(fit_timeline(&times, now, LimitPolicy::Refuse).is_err());
+        assert_eq!(
+            fit_timeline(&times, now, LimitPolicy::Clamp).unwrap(),
+            vec![now - Duration::minutes(5), now]
+        );
+        assert_eq!(
+            fit_timeline(&times, now, LimitPolicy::Shift).unwrap(),
+            vec![now - Duration::minutes(10), now]
+        );
+
+        let old_times = vec![now - Duration::days(20), now - Duration::days(19)];
+        let shifted = fit_timeline(&old_times, now, LimitPolicy::Shift).unwrap();
+        assert!(shifted
+            .iter()
+            .all(|&when| check_timestamp(when, now).is_none()));
+        assert_eq!(shifted[1] - shifted[0], Duration::days(1));
+
+        let old_times = vec![
+            now - Duration::days(20),
+            now - Duration::days(20) + Duration::minutes(4),
+            now - Duration::hours(1),
+            now + Duration::minutes(5),
+            now + Duration::minutes(9),
+        ];
+        let oldest = now - MAX_SCROBBLE_AGE + SAFETY_MARGIN;
+        assert_eq!(
+            fit_timeline(&old_times, now, LimitPolicy::Clamp).unwrap(),
+            vec![
+                oldest,
+                oldest + Duration::minutes(4),
+                now - Duration::hours(1),
+                now - Duration::minutes(4),
+                now
+            ]
+        );
+
+        let long_times = vec![now - Duration::days(20), now];
+        assert!(fit_timeline(&long_times, now, LimitPolicy::Shift).is_err());
+
+        let valid_times = vec![now - Duration::hours(1)];
+        assert_eq!(
+            fit_timeline(&valid_times, now, LimitPolicy::Refuse).unwrap(),
+            valid_times
+        );
+    }
+}
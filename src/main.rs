@@ -1,21 +1,63 @@
+mod audiofiles;
 mod auth;
+mod credentials;
+mod cue;
+mod import;
 mod lastfmapi;
+mod limits;
+mod musicbrainz;
+mod playlist;
+mod prompt;
+mod queue;
 mod scrobbler;
+mod scrobblerlog;
+mod selection;
 mod utils;
+mod webauth;
 
-use crate::auth::authenticate;
-use crate::scrobbler::{scrobble_album, scrobble_track, scrobble_url};
+use crate::auth::{
+    auth_status, authenticate, authenticate_mobile, for_each_profile, list_profiles, logout,
+    Profile,
+};
+use crate::credentials::CredentialStore;
+use crate::import::{ColumnMapping, ImportFormat};
+use crate::lastfmapi::ApiError;
+use crate::limits::LimitPolicy;
+use crate::queue::{drop_from_queue, list_queue};
+use crate::scrobbler::{
+    flush_queue, import_file, now_playing, scrobble_album, scrobble_cue, scrobble_files,
+    scrobble_log, scrobble_playlist, scrobble_track, scrobble_url, Accounts, ScrobbleOptions,
+    Timing,
+};
+use crate::scrobblerlog::AfterLog;
+use crate::selection::TrackSelection;
+use crate::utils::parse_datetime;
 use anyhow::Context;
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
 use env_logger::Env;
 use log::{error, info};
+use std::path::PathBuf;
 use time::Duration;
 
 #[derive(Debug, Clone, Parser)]
+struct Cli {
+    /// Named profile of a Last.fm account
+    #[arg(long, global = true)]
+    profile: Option<String>,
+
+    /// Run the command for every authenticated profile
+    #[arg(long, global = true, conflicts_with = "profile")]
+    all_profiles: bool,
+
+    #[command(subcommand)]
+    command: CliArgs,
+}
+
+#[derive(Debug, Clone, Subcommand)]
 enum CliArgs {
     #[command(about = "Scrobble album of artist or track of artist to Last.fm")]
     Scrobble {
-        /// Artist name
+        /// Artist name, "Various Artists" for compilations
         #[arg(long)]
         artist: String,
 
@@ -27,13 +69,19 @@ enum CliArgs {
         #[arg(long)]
         track: Option<String>,
 
-        /// Dry run mode (no writes done)
-        #[arg(short, long)]
-        dryrun: bool,
+        /// Search albums by name and choose one
+        #[arg(long, requires = "album")]
+        search: bool,
 
-        /// Start time
-        #[arg(long)]
-        start: Option<String>,
+        /// Duration of tracks unknown to Last.fm and MusicBrainz
+        #[arg(long, requires = "album", value_parser = humantime::parse_duration)]
+        default_duration: Option<std::time::Duration>,
+
+        #[command(flatten)]
+        options: ScrobbleArgs,
+
+        #[command(flatten)]
+        selection: SelectionArgs,
     },
 
     #[command(about = "Scrobble album from given URL to Last.fm")]
@@ -42,24 +90,340 @@ enum CliArgs {
         #[arg(long)]
         url: String,
 
+        /// Duration of tracks unknown to Last.fm and MusicBrainz
+        #[arg(long, value_parser = humantime::parse_duration)]
+        default_duration: Option<std::time::Duration>,
+
+        #[command(flatten)]
+        options: ScrobbleArgs,
+
+        #[command(flatten)]
+        selection: SelectionArgs,
+    },
+
+    #[command(about = "Scrobble album described by a CUE sheet to Last.fm")]
+    ScrobbleCue {
+        /// Path to .cue file
+        #[arg(long)]
+        file: PathBuf,
+
+        /// Duration of the last track which cannot be computed from the sheet
+        #[arg(long, value_parser = humantime::parse_duration)]
+        last_duration: Option<std::time::Duration>,
+
+        #[command(flatten)]
+        options: ScrobbleArgs,
+
+        #[command(flatten)]
+        selection: SelectionArgs,
+    },
+
+    #[command(about = "Scrobble tracks of M3U/M3U8/PLS playlist to Last.fm")]
+    ScrobblePlaylist {
+        /// Path to playlist file
+        #[arg(long)]
+        file: PathBuf,
+
+        /// Duration of tracks unknown to Last.fm and MusicBrainz
+        #[arg(long, value_parser = humantime::parse_duration)]
+        default_duration: Option<std::time::Duration>,
+
+        #[command(flatten)]
+        options: ScrobbleArgs,
+
+        #[command(flatten)]
+        selection: SelectionArgs,
+    },
+
+    #[command(about = "Scrobble local audio files using their tags to Last.fm")]
+    ScrobbleFiles {
+        /// Audio files or directories with them
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        #[command(flatten)]
+        options: ScrobbleArgs,
+
+        #[command(flatten)]
+        selection: SelectionArgs,
+    },
+
+    #[command(about = "Scrobble listened tracks from a portable player .scrobbler.log")]
+    ScrobbleLog {
+        /// Path to .scrobbler.log file
+        #[arg(long)]
+        file: PathBuf,
+
+        /// Dry run mode (no writes done)
+        #[arg(short, long)]
+        dryrun: bool,
+
+        /// Scrobble even tracks which look already scrobbled
+        #[arg(long)]
+        force: bool,
+
+        /// What to do with the log file after scrobbling
+        #[arg(long, value_enum, default_value_t)]
+        after: AfterLog,
+    },
+
+    #[command(about = "Import scrobbles from a CSV/JSON listening log to Last.fm")]
+    Import {
+        /// Path to the listening log
+        #[arg(long)]
+        file: PathBuf,
+
+        /// Format of the listening log
+        #[arg(long, value_enum)]
+        format: ImportFormat,
+
+        /// Field delimiter of CSV
+        #[arg(long, default_value_t = ',')]
+        delimiter: char,
+
+        #[command(flatten)]
+        columns: ColumnArgs,
+
         /// Dry run mode (no writes done)
         #[arg(short, long)]
         dryrun: bool,
 
-        /// Start time
+        /// Scrobble even tracks which look already scrobbled
         #[arg(long)]
-        start: Option<String>,
+        force: bool,
     },
 
-    #[command(about = "Authenticate with Last.fm desktop API")]
+    #[command(about = "Show a track as playing now on Last.fm")]
+    NowPlaying {
+        /// Artist name
+        #[arg(long)]
+        artist: String,
+
+        /// Track name
+        #[arg(long)]
+        track: String,
+
+        /// Album name
+        #[arg(long)]
+        album: Option<String>,
+
+        /// Track duration
+        #[arg(long, value_parser = humantime::parse_duration)]
+        duration: Option<std::time::Duration>,
+
+        /// Wait for the track duration and scrobble it
+        #[arg(long, requires = "duration")]
+        then_scrobble: bool,
+
+        /// Dry run mode (no writes done)
+        #[arg(short, long)]
+        dryrun: bool,
+    },
+
+    #[command(about = "Send scrobbles queued while offline to Last.fm")]
+    Flush {
+        /// Dry run mode (no writes done)
+        #[arg(short, long)]
+        dryrun: bool,
+
+        /// What to do with queued timestamps older than 14 days or in the future
+        #[arg(long, value_enum, default_value_t)]
+        out_of_range: LimitPolicy,
+    },
+
+    #[command(about = "Inspect or edit the offline scrobble queue")]
+    Queue {
+        #[command(subcommand)]
+        command: QueueCommand,
+    },
+
+    #[command(
+        about = "Authenticate with Last.fm desktop API",
+        args_conflicts_with_subcommands = true,
+        subcommand_negates_reqs = true
+    )]
     Auth {
+        #[command(subcommand)]
+        command: Option<AuthCommand>,
+
         /// API key
-        #[arg(long)]
-        api_key: String,
+        #[arg(long, required = true)]
+        api_key: Option<String>,
 
         /// Secret key
-        #[arg(long)]
-        secret_key: String,
+        #[arg(long, required = true)]
+        secret_key: Option<String>,
+
+        /// Where to keep secret and session keys [default: as before or plaintext]
+        #[arg(long, value_enum)]
+        store: Option<CredentialStore>,
+
+        /// Catch the confirmation with a local callback server instead of waiting for a key press
+        #[arg(long, conflicts_with = "username")]
+        callback: bool,
+
+        /// Last.fm username to authenticate without a browser
+        #[arg(long, requires = "password_stdin")]
+        username: Option<String>,
+
+        /// Read the password from the first line of stdin
+        #[arg(long, requires = "username")]
+        password_stdin: bool,
+    },
+}
+
+/// Common scrobbling options
+#[derive(Debug, Clone, Args)]
+struct ScrobbleArgs {
+    /// Dry run mode (no writes done)
+    #[arg(short, long)]
+    dryrun: bool,
+
+    /// Scrobble even tracks which look already scrobbled
+    #[arg(long)]
+    force: bool,
+
+    /// Start time
+    #[arg(long, conflicts_with_all = ["started_at", "finished_at"])]
+    start: Option<String>,
+
+    /// Listening start as RFC3339 or local date-time, e.g. "2026-10-15 21:30"
+    #[arg(long, conflicts_with = "finished_at")]
+    started_at: Option<String>,
+
+    /// Listening end as RFC3339 or local date-time, e.g. "2026-10-15 22:15"
+    #[arg(long)]
+    finished_at: Option<String>,
+
+    /// What to do with timestamps older than 14 days or in the future
+    #[arg(long, value_enum, default_value_t)]
+    out_of_range: LimitPolicy,
+
+    /// Do not ask questions, e.g. take the first found album
+    #[arg(short, long)]
+    yes: bool,
+
+    /// Apply Last.fm corrections of misspelled artist and track names
+    #[arg(long)]
+    autocorrect: bool,
+}
+
+impl ScrobbleArgs {
+    fn into_options(self) -> anyhow::Result<ScrobbleOptions> {
+        let timing = if let Some(started_at) = self.started_at {
+            Timing::StartedAt(parse_datetime(&started_at)?)
+        } else if let Some(finished_at) = self.finished_at {
+            Timing::FinishedAt(parse_datetime(&finished_at)?)
+        } else {
+            Timing::Ago(start_to_duration(self.start)?.unwrap_or(Duration::ZERO))
+        };
+        Ok(ScrobbleOptions {
+            dryrun: self.dryrun,
+            force: self.force,
+            timing,
+            limits: self.out_of_range,
+            yes: self.yes,
+            autocorrect: self.autocorrect,
+        })
+    }
+}
+
+/// Album tracks to scrobble
+#[derive(Debug, Clone, Args)]
+struct SelectionArgs {
+    /// Scrobble only these tracks, e.g. 1-5,7
+    #[arg(long)]
+    tracks: Option<String>,
+
+    /// Do not scrobble these tracks, e.g. 3 or 3,8-9
+    #[arg(long)]
+    skip: Option<String>,
+
+    /// First track to scrobble
+    #[arg(long)]
+    from_track: Option<usize>,
+
+    /// Last track to scrobble
+    #[arg(long)]
+    to_track: Option<usize>,
+
+    /// Scrobble only this disc of a multi-disc release
+    #[arg(long)]
+    disc: Option<u32>,
+}
+
+impl SelectionArgs {
+    fn into_selection(self) -> anyhow::Result<TrackSelection> {
+        Ok(TrackSelection {
+            disc: self.disc,
+            ..TrackSelection::parse(self.tracks, self.skip, self.from_track, self.to_track)?
+        })
+    }
+}
+
+/// Columns or keys of a listening log
+#[derive(Debug, Clone, Args)]
+struct ColumnArgs {
+    /// Column with artist name
+    #[arg(long, default_value = "artist")]
+    artist_column: String,
+
+    /// Column with track name
+    #[arg(long, default_value = "track")]
+    track_column: String,
+
+    /// Column with album name
+    #[arg(long, default_value = "album")]
+    album_column: String,
+
+    /// Column with unix timestamp, RFC3339 or local date-time
+    #[arg(long, default_value = "timestamp")]
+    timestamp_column: String,
+
+    /// Column with time ago like "2h 30m", used when timestamp is empty
+    #[arg(long, default_value = "offset")]
+    offset_column: String,
+
+    /// Column with track duration in seconds or like "4m 12s"
+    #[arg(long, default_value = "duration")]
+    duration_column: String,
+}
+
+impl ColumnArgs {
+    fn into_mapping(self) -> ColumnMapping {
+        ColumnMapping {
+            artist: self.artist_column,
+            track: self.track_column,
+            album: self.album_column,
+            timestamp: self.timestamp_column,
+            offset: self.offset_column,
+            duration: self.duration_column,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Subcommand)]
+enum AuthCommand {
+    #[command(about = "Show saved credentials and check the session")]
+    Status,
+
+    #[command(about = "Remove saved credentials")]
+    Logout,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+enum QueueCommand {
+    #[command(about = "List queued scrobbles")]
+    List,
+
+    #[command(about = "Drop scrobbles from the queue")]
+    Drop {
+        /// Positions of scrobbles as shown by list
+        positions: Vec<usize>,
+
+        /// Drop all queued scrobbles
+        #[arg(long, conflicts_with = "positions")]
+        all: bool,
     },
 }
 
@@ -75,39 +439,226 @@ fn start_to_duration(arg: Option<String>) -> anyhow::Result<Option<Duration>> {
     Ok(opt_duration)
 }
 
-fn run(cli_args: CliArgs) -> anyhow::Result<()> {
+/// The only profile of a command which cannot run for every profile
+fn single_profile(profiles: &[Profile]) -> anyhow::Result<&Profile> {
+    match profiles {
+        [profile] => Ok(profile),
+        _ => anyhow::bail!("Authenticate or log out profiles one by one with --profile"),
+    }
+}
+
+fn run(cli_args: CliArgs, profiles: &[Profile]) -> anyhow::Result<()> {
     match cli_args {
         CliArgs::Auth {
+            command: Some(AuthCommand::Status),
+            ..
+        } => for_each_profile(profiles, |_, profile| auth_status(profile)),
+        CliArgs::Auth {
+            command: Some(AuthCommand::Logout),
+            ..
+        } => logout(single_profile(profiles)?),
+        CliArgs::Auth {
+            command: None,
+            api_key: Some(api_key),
+            secret_key: Some(secret_key),
+            store,
+            callback: _,
+            username: Some(username),
+            password_stdin: _,
+        } => authenticate_mobile(
+            single_profile(profiles)?,
             api_key,
             secret_key,
-        } => authenticate(api_key, secret_key),
+            username,
+            store,
+        ),
+        CliArgs::Auth {
+            command: None,
+            api_key: Some(api_key),
+            secret_key: Some(secret_key),
+            store,
+            callback,
+            username: None,
+            password_stdin: _,
+        } => authenticate(
+            single_profile(profiles)?,
+            api_key,
+            secret_key,
+            store,
+            callback,
+        ),
+        CliArgs::Auth { .. } => {
+            anyhow::bail!("Specify --api-key and --secret-key");
+        }
         CliArgs::Scrobble {
             artist,
             album,
             track: _,
-            dryrun,
-            start,
-        } if album.is_some() => {
-            scrobble_album(artist, album.unwrap(), dryrun, start_to_duration(start)?)
-        }
+            search,
+            default_duration,
+            options,
+            selection,
+        } if album.is_some() => scrobble_album(
+            &Accounts::connect(profiles)?,
+            artist,
+            album.unwrap(),
+            search,
+            default_duration,
+            &options.into_options()?,
+            &selection.into_selection()?,
+        ),
         CliArgs::Scrobble {
             artist,
             album: _,
             track,
-            dryrun,
-            start,
+            search: _,
+            default_duration: _,
+            options,
+            selection,
         } if track.is_some() => {
-            scrobble_track(artist, track.unwrap(), dryrun, start_to_duration(start)?)
+            if !selection.into_selection()?.is_all() {
+                anyhow::bail!("Track selection is only supported for albums");
+            }
+            scrobble_track(
+                &Accounts::connect(profiles)?,
+                artist,
+                track.unwrap(),
+                &options.into_options()?,
+            )
         }
         CliArgs::Scrobble { .. } => {
             anyhow::bail!("Wrong arguments");
         }
-        CliArgs::ScrobbleUrl { url, dryrun, start } => {
-            scrobble_url(url, dryrun, start_to_duration(start)?)
+        CliArgs::ScrobbleUrl {
+            url,
+            default_duration,
+            options,
+            selection,
+        } => scrobble_url(
+            &Accounts::connect(profiles)?,
+            url,
+            default_duration,
+            &options.into_options()?,
+            &selection.into_selection()?,
+        ),
+        CliArgs::ScrobbleCue {
+            file,
+            last_duration,
+            options,
+            selection,
+        } => scrobble_cue(
+            &Accounts::connect(profiles)?,
+            file,
+            last_duration,
+            &options.into_options()?,
+            &selection.into_selection()?,
+        ),
+        CliArgs::ScrobblePlaylist {
+            file,
+            default_duration,
+            options,
+            selection,
+        } => scrobble_playlist(
+            &Accounts::connect(profiles)?,
+            file,
+            default_duration,
+            &options.into_options()?,
+            &selection.into_selection()?,
+        ),
+        CliArgs::ScrobbleFiles {
+            paths,
+            options,
+            selection,
+        } => scrobble_files(
+            &Accounts::connect(profiles)?,
+            paths,
+            &options.into_options()?,
+            &selection.into_selection()?,
+        ),
+        CliArgs::ScrobbleLog {
+            file,
+            dryrun,
+            force,
+            after,
+        } => scrobble_log(
+            &Accounts::connect(profiles)?,
+            file,
+            &ScrobbleOptions {
+                dryrun,
+                force,
+                ..ScrobbleOptions::default()
+            },
+            after,
+        ),
+        CliArgs::Import {
+            file,
+            format,
+            delimiter,
+            columns,
+            dryrun,
+            force,
+        } => import_file(
+            &Accounts::connect(profiles)?,
+            file,
+            format,
+            delimiter,
+            &columns.into_mapping(),
+            &ScrobbleOptions {
+                dryrun,
+                force,
+                ..ScrobbleOptions::default()
+            },
+        ),
+        CliArgs::NowPlaying {
+            artist,
+            track,
+            album,
+            duration,
+            then_scrobble,
+            dryrun,
+        } => now_playing(
+            &Accounts::connect(profiles)?,
+            artist,
+            track,
+            album,
+            duration,
+            then_scrobble,
+            dryrun,
+        ),
+        CliArgs::Flush {
+            dryrun,
+            out_of_range,
+        } => Accounts::connect(profiles)?
+            .for_each(|profile, api| flush_queue(profile, api, dryrun, out_of_range)),
+        CliArgs::Queue {
+            command: QueueCommand::List,
+        } => for_each_profile(profiles, |_, profile| list_queue(profile)),
+        CliArgs::Queue {
+            command: QueueCommand::Drop { positions, all },
+        } => {
+            if positions.is_empty() && !all {
+                anyhow::bail!("Specify positions to drop or --all");
+            }
+            for_each_profile(profiles, |_, profile| {
+                drop_from_queue(profile, &positions, all)
+            })
         }
     }
 }
 
+/// Run a command for the chosen profile or for every profile
+fn run_profiles(cli: Cli) -> anyhow::Result<()> {
+    let profiles = if cli.all_profiles {
+        list_profiles()?
+    } else {
+        vec![Profile::new(cli.profile)?]
+    };
+    if profiles.is_empty() {
+        anyhow::bail!("No authenticated profiles, run `auth` first");
+    }
+    run(cli.command, &profiles)
+}
+
 /// Entry point
 fn main() -> Result<(), anyhow::Error> {
     env_logger::Builder::from_env(Env::default().default_filter_or("info"))
@@ -119,8 +670,8 @@ fn main() -> Result<(), anyhow::Error> {
         .format_timestamp(None)
         .init();
 
-    let cli_args = CliArgs::parse();
-    let result = run(cli_args);
+    let cli = Cli::parse();
+    let result = run_profiles(cli);
     match result {
         Ok(_) => {
             info!("Done");
@@ -128,6 +679,15 @@ fn main() -> Result<(), anyhow::Error> {
         }
         Err(err) => {
             error!("Error: {}", err);
+            match err.downcast_ref::<ApiError>() {
+                Some(ApiError::InvalidSession(_)) | Some(ApiError::AuthenticationFailed(_)) => {
+                    error!("Session has expired or was revoked, run `auth` again")
+                }
+                Some(ApiError::InvalidApiKey(_)) => {
+                    error!("API key is not valid, run `auth` again with a correct key")
+                }
+                _ => {}
+            }
             Err(err)
         }
     }
@@ -146,4 +706,56 @@ mod tests {
         assert!(start_to_duration(Some("30minutes".to_string())).is_ok());
         assert!(start_to_duration(Some("-1h".to_string())).is_err());
     }
+
+    #[test]
+    fn test_profile_args() {
+        let cli =
+            Cli::try_parse_from(["offline-scrobbler", "flush", "--profile", "alice"]).unwrap();
+        assert_eq!(cli.profile.as_deref(), Some("alice"));
+        let cli = Cli::try_parse_from(["offline-scrobbler", "--all-profiles", "flush"]).unwrap();
+        assert!(cli.all_profiles);
+        assert!(Cli::try_parse_from([
+            "offline-scrobbler",
+            "--all-profiles",
+            "--profile",
+            "alice",
+            "flush"
+        ])
+        .is_err());
+        assert!(Profile::new(Some("../alice".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_auth_args() {
+        let cli = Cli::try_parse_from(["offline-scrobbler", "auth", "status"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            CliArgs::Auth {
+                command: Some(AuthCommand::Status),
+                ..
+            }
+        ));
+        let cli = Cli::try_parse_from([
+            "offline-scrobbler",
+            "auth",
+            "--api-key",
+            "key",
+            "--secret-key",
+            "secret",
+        ])
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            CliArgs::Auth {
+                command: None,
+                api_key: Some(_),
+                ..
+            }
+        ));
+        assert!(Cli::try_parse_from(["offline-scrobbler", "auth"]).is_err());
+        assert!(
+            Cli::try_parse_from(["offline-scrobbler", "auth", "--api-key", "key", "logout"])
+                .is_err()
+        );
+    }
 }
@@ -0,0 +1,108 @@
+use anyhow::Context;
+use log::debug;
+use reqwest::blocking::Client;
+use serde_json::Value;
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+pub const MUSICBRAINZ_HOST: &str = "https://musicbrainz.org";
+
+/// MusicBrainz rejects requests without a meaningful user agent
+const USER_AGENT: &str = concat!(
+    "offline-scrobbler/",
+    env!("CARGO_PKG_VERSION"),
+    " ( https://github.com/theirix/offline-scrobbler )"
+);
+
+/// MusicBrainz allows one request per second
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// MusicBrainz web service client
+pub struct MusicBrainzApi {
+    client: Client,
+    api_host: String,
+    last_request: Cell<Option<Instant>>,
+}
+
+impl MusicBrainzApi {
+    pub fn new(api_host: String) -> Self {
+        Self {
+            client: Client::new(),
+            api_host,
+            last_request: Cell::new(None),
+        }
+    }
+
+    /// Length of a recording in seconds if known
+    pub fn get_recording_length(&self, mbid: &str) -> anyhow::Result<Option<i64>> {
+        if let Some(last_request) = self.last_request.get() {
+            let elapsed = last_request.elapsed();
+            if elapsed < REQUEST_INTERVAL {
+                std::thread::sleep(REQUEST_INTERVAL - elapsed);
+            }
+        }
+        self.last_request.set(Some(Instant::now()));
+
+        let url = format!(
+            "{}/ws/2/recording/{}?fmt=json",
+            self.api_host,
+            urlencoding::encode(mbid)
+        );
+        let response = self
+            .client
+            .get(url)
+            .header("User-Agent", USER_AGENT)
+            .send()?
+            .error_for_status()
+            .with_context(|| format!("Cannot find recording {}", mbid))?;
+        let resp: Value = serde_json::from_str(&response.text()?)?;
+        debug!("Resp {}", resp);
+        // Length is in milliseconds
+        Ok(resp
+            .get("length")
+            .and_then(|length| length.as_i64())
+            .filter(|&length| length > 0)
+            .map(|length| (length + 500) / 1000))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use httpmock::prelude::*;
+    use test_log::test;
+
+    #[test]
+    fn test_get_recording_length() {
+        let server = MockServer::start();
+        let mock_recording = server.mock(|when, then| {
+            when.method(GET)
+                .path("/ws/2/recording/7cbdba3e-2b7d-4f85-a05a-5d4bf1b0ab67")
+                .query_param("fmt", "json")
+                .header_exists("User-Agent");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(
+                    r#"{"id": "7cbdba3e-2b7d-4f85-a05a-5d4bf1b0ab67", "title": "Eden",
+                        "length": 241466, "video": false, "disambiguation": ""}"#,
+                );
+        });
+        let mock_missing = server.mock(|when, then| {
+            when.method(GET).path("/ws/2/recording/unknown");
+            then.status(404)
+                .header("content-type", "application/json")
+                .body(r#"{"error": "Not Found"}"#);
+        });
+
+        let api = MusicBrainzApi::new(format!("http://{}", server.address()));
+        assert_eq!(
+            api.get_recording_length("7cbdba3e-2b7d-4f85-a05a-5d4bf1b0ab67")
+                .unwrap(),
+            Some(241)
+        );
+        assert!(api.get_recording_length("unknown").is_err());
+        mock_recording.assert();
+        mock_missing.assert();
+    }
+}
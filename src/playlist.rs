@@ -0,0 +1,194 @@
+use crate::lastfmapi::{DurationSource, Track, DEFAULT_TRACK_DURATION};
+use anyhow::Context;
+use log::warn;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Parse M3U/M3U8 or PLS playlist into tracks with artists
+pub fn parse_playlist(content: &str) -> anyhow::Result<Vec<Track>> {
+    let content = content.trim_start_matches('\u{feff}');
+    let tracks = if content
+        .trim_start()
+        .to_lowercase()
+        .starts_with("[playlist]")
+    {
+        parse_pls(content)?
+    } else {
+        parse_m3u(content)?
+    };
+    if tracks.is_empty() {
+        anyhow::bail!("No tracks in playlist");
+    }
+    Ok(tracks)
+}
+
+/// Parse M3U with optional #EXTINF:duration,Artist - Title lines
+fn parse_m3u(content: &str) -> anyhow::Result<Vec<Track>> {
+    let mut tracks = Vec::new();
+    let mut extinf: Option<(i64, String)> = None;
+    for (idx, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            let (duration, title) = info.split_once(',').unwrap_or((info, ""));
+            // Duration may be followed by attributes like tvg-id="..."
+            let duration = duration
+                .split_whitespace()
+                .next()
+                .and_then(|d| d.parse::<f64>().ok())
+                .map_or(-1, |d| d.round() as i64);
+            extinf = Some((duration, title.trim().to_string()));
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+        // A path or URL of an entry
+        let (duration, title) = extinf.take().unwrap_or((-1, String::new()));
+        let title = if title.is_empty() {
+            file_stem(line)
+        } else {
+            title
+        };
+        // A single odd entry should not spoil the whole playlist
+        match make_track(&title, duration, tracks.len() + 1) {
+            Ok(track) => tracks.push(track),
+            Err(e) => warn!("Skipping line {} '{}': {}", idx + 1, line, e),
+        }
+    }
+    Ok(tracks)
+}
+
+/// An entry of PLS playlist collected from numbered keys
+#[derive(Default)]
+struct PlsEntry {
+    file: Option<String>,
+    title: Option<String>,
+    length: Option<i64>,
+}
+
+/// Parse PLS with FileN, TitleN and LengthN entries
+fn parse_pls(content: &str) -> anyhow::Result<Vec<Track>> {
+    let mut entries: BTreeMap<u32, PlsEntry> = BTreeMap::new();
+    for line in content.lines() {
+        let Some((key, value)) = line.trim().split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_lowercase();
+        let value = value.trim().to_string();
+        // Split a key like "title12" into a name and an entry number
+        let name_len = key.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+        let Ok(number) = key[name_len..].parse::<u32>() else {
+            continue;
+        };
+        let entry = entries.entry(number).or_default();
+        match &key[..name_len] {
+            "file" => entry.file = Some(value),
+            "title" => entry.title = Some(value),
+            "length" => entry.length = value.parse().ok(),
+            _ => {}
+        }
+    }
+    let mut tracks = Vec::with_capacity(entries.len());
+    for (number, entry) in entries {
+        let track = entry
+            .title
+            .or_else(|| entry.file.as_deref().map(file_stem))
+            .context("No title or file")
+            .and_then(|title| make_track(&title, entry.length.unwrap_or(-1), tracks.len() + 1));
+        match track {
+            Ok(track) => tracks.push(track),
+            Err(e) => warn!("Skipping entry {}: {}", number, e),
+        }
+    }
+    Ok(tracks)
+}
+
+/// Build a track from "Artist - Title" string and a duration in seconds, -1 if unknown
+fn make_track(title: &str, duration: i64, number: usize) -> anyhow::Result<Track> {
+    let (artist, title) = title.split_once(" - ").with_context(|| {
+        format!(
+            "Cannot find artist in '{}', expected 'Artist - Title'",
+            title
+        )
+    })?;
+    Ok(Track {
+        title: title.trim().to_string(),
+        artist: Some(artist.trim().to_string()),
+        album_artist: None,
+        duration: if duration > 0 {
+            duration
+        } else {
+            DEFAULT_TRACK_DURATION
+        },
+        duration_source: if duration > 0 {
+            DurationSource::Release
+        } else {
+            DurationSource::Default
+        },
+        number: Some(number as u32),
+        disc: None,
+        mbid: None,
+    })
+}
+
+/// File name without directories and extension
+fn file_stem(path: &str) -> String {
+    // Playlists made on Windows use backslashes
+    let name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+    Path::new(name)
+        .file_stem()
+        .map_or(name.to_string(), |stem| stem.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn test_parse_m3u() {
+        let content = "#EXTM3U\n\
+            #EXTINF:241,Hooverphonic - Eden\n\
+            music/Hooverphonic/03 Eden.flac\n\
+            #EXTINF:-1,Massive Attack - Teardrop\n\
+            music/Massive Attack/Teardrop.mp3\n\
+            music/track01.mp3\n\
+            C:\\Music\\Portishead - Roads.mp3\n";
+        let tracks = parse_playlist(content).unwrap();
+        assert_eq!(tracks.len(), 3);
+        assert_eq!(tracks[0].artist.as_deref(), Some("Hooverphonic"));
+        assert_eq!(tracks[0].title, "Eden");
+        assert_eq!(tracks[0].duration, 241);
+        assert_eq!(tracks[1].artist.as_deref(), Some("Massive Attack"));
+        assert_eq!(tracks[1].duration, DEFAULT_TRACK_DURATION);
+        assert_eq!(tracks[2].artist.as_deref(), Some("Portishead"));
+        assert_eq!(tracks[2].title, "Roads");
+        assert_eq!(tracks[2].number, Some(3));
+
+        assert!(parse_playlist("#EXTM3U\nmusic/track01.mp3\n").is_err());
+        assert!(parse_playlist("#EXTM3U\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_pls() {
+        let content = "[playlist]\n\
+            File1=music/eden.flac\n\
+            Title1=Hooverphonic - Eden\n\
+            Length1=241\n\
+            File2=music/Massive Attack - Teardrop.mp3\n\
+            Length2=-1\n\
+            NumberOfEntries=2\n\
+            Version=2\n";
+        let tracks = parse_playlist(content).unwrap();
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(tracks[0].title, "Eden");
+        assert_eq!(tracks[0].duration, 241);
+        assert_eq!(tracks[1].artist.as_deref(), Some("Massive Attack"));
+        assert_eq!(tracks[1].title, "Teardrop");
+        assert_eq!(tracks[1].duration, DEFAULT_TRACK_DURATION);
+    }
+}
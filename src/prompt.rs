@@ -0,0 +1,73 @@
+use log::warn;
+use std::io::{self, Write};
+
+/// What the user answered to a choice
+#[derive(Debug, PartialEq)]
+enum Choice {
+    /// Zero-based index of a chosen option
+    Option(usize),
+    Cancel,
+    Invalid,
+}
+
+/// Whether questions can be asked
+pub fn is_interactive() -> bool {
+    atty::is(atty::Stream::Stdin)
+}
+
+/// Ask the user to choose one of `count` numbered options, None if cancelled
+pub fn choose(count: usize) -> anyhow::Result<Option<usize>> {
+    if !is_interactive() {
+        anyhow::bail!("Cannot ask without a terminal, use --yes to proceed without asking");
+    }
+    loop {
+        let line = ask(&format!("Choose 1-{} or 0 to cancel: ", count))?;
+        if line.is_empty() {
+            return Ok(None);
+        }
+        match parse_choice(&line, count) {
+            Choice::Option(idx) => return Ok(Some(idx)),
+            Choice::Cancel => return Ok(None),
+            Choice::Invalid => warn!("Wrong choice '{}'", line.trim()),
+        }
+    }
+}
+
+/// Ask a yes/no question, no is the default answer
+pub fn confirm(question: &str) -> anyhow::Result<bool> {
+    let answer = ask(&format!("{} [y/N]: ", question))?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Print a prompt and read a line, empty at the end of input
+fn ask(prompt: &str) -> anyhow::Result<String> {
+    eprint!("{}", prompt);
+    io::stderr().flush()?;
+    let mut line = String::new();
+    io::stdin().read_line(&mut line)?;
+    Ok(line)
+}
+
+fn parse_choice(input: &str, count: usize) -> Choice {
+    match input.trim().parse::<usize>() {
+        Ok(0) => Choice::Cancel,
+        Ok(number) if number <= count => Choice::Option(number - 1),
+        _ => Choice::Invalid,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn test_parse_choice() {
+        assert_eq!(parse_choice("1\n", 3), Choice::Option(0));
+        assert_eq!(parse_choice(" 3 ", 3), Choice::Option(2));
+        assert_eq!(parse_choice("0", 3), Choice::Cancel);
+        assert_eq!(parse_choice("4", 3), Choice::Invalid);
+        assert_eq!(parse_choice("yes", 3), Choice::Invalid);
+    }
+}
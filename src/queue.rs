@@ -0,0 +1,169 @@
+use crate::auth::Profile;
+use crate::lastfmapi::Scrobble;
+use anyhow::Context;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use time::macros::format_description;
+
+/// Scrobbles saved on disk to be sent later
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct ScrobbleQueue {
+    #[serde(default)]
+    pub scrobbles: Vec<Scrobble>,
+}
+
+impl ScrobbleQueue {
+    /// Load a queue from file, an absent file means an empty queue
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+        let serialized = fs::read_to_string(path)?;
+        let mut queue: ScrobbleQueue =
+            toml::from_str(&serialized).context("cannot parse queue file")?;
+        queue.sort();
+        Ok(queue)
+    }
+
+    /// Save a queue to file, an empty queue removes the file
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if self.scrobbles.is_empty() {
+            if path.is_file() {
+                fs::remove_file(path)?;
+            }
+            return Ok(());
+        }
+        let serialized: String = toml::to_string(self)?;
+        fs::write(path, serialized)?;
+        Ok(())
+    }
+
+    /// Keep scrobbles in timestamp order
+    pub fn sort(&mut self) {
+        self.scrobbles.sort_by_key(|scrobble| scrobble.when);
+    }
+
+    pub fn extend(&mut self, scrobbles: &[Scrobble]) {
+        self.scrobbles.extend_from_slice(scrobbles);
+        self.sort();
+    }
+
+    /// Remove scrobbles by their 1-based positions
+    pub fn remove(&mut self, positions: &[usize]) -> anyhow::Result<()> {
+        if let Some(position) = positions
+            .iter()
+            .find(|&&position| position == 0 || position > self.scrobbles.len())
+        {
+            anyhow::bail!("No scrobble #{} in the queue", position);
+        }
+        let mut idx = 0;
+        self.scrobbles.retain(|_| {
+            idx += 1;
+            !positions.contains(&idx)
+        });
+        Ok(())
+    }
+}
+
+/// Provide path to queue file next to auth config
+pub fn queue_file(profile: &Profile) -> anyhow::Result<PathBuf> {
+    Ok(profile.dir()?.join("queue.toml"))
+}
+
+/// Append scrobbles to the on-disk queue of a profile
+pub fn enqueue(profile: &Profile, scrobbles: &[Scrobble]) -> anyhow::Result<()> {
+    let path = queue_file(profile)?;
+    let mut queue = ScrobbleQueue::load(&path)?;
+    queue.extend(scrobbles);
+    queue.save(&path)?;
+    info!(
+        "Queued {} scrobbles to {}, {} in total",
+        scrobbles.len(),
+        path.display(),
+        queue.scrobbles.len()
+    );
+    Ok(())
+}
+
+/// Show queued scrobbles
+pub fn list_queue(profile: &Profile) -> anyhow::Result<()> {
+    let path = queue_file(profile)?;
+    let queue = ScrobbleQueue::load(&path)?;
+    if queue.scrobbles.is_empty() {
+        info!("Queue is empty");
+        return Ok(());
+    }
+    for (idx, scrobble) in queue.scrobbles.iter().enumerate() {
+        info!(
+            "#{} '{}' of artist '{}' at {}",
+            idx + 1,
+            &scrobble.track,
+            &scrobble.artist,
+            scrobble.when.format(format_description!(
+                "[year]-[month]-[day] [hour]:[minute]:[second]"
+            ))?,
+        );
+    }
+    Ok(())
+}
+
+/// Drop selected or all scrobbles from the queue
+pub fn drop_from_queue(profile: &Profile, positions: &[usize], all: bool) -> anyhow::Result<()> {
+    let path = queue_file(profile)?;
+    let mut queue = ScrobbleQueue::load(&path)?;
+    let count_before = queue.scrobbles.len();
+    if all {
+        queue.scrobbles.clear();
+    } else {
+        queue.remove(positions)?;
+    }
+    queue.save(&path)?;
+    info!(
+        "Dropped {} scrobbles from the queue",
+        count_before - queue.scrobbles.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use test_log::test;
+    use time::ext::NumericalDuration;
+    use time::OffsetDateTime;
+
+    fn make_scrobble(track: &str, when: OffsetDateTime) -> Scrobble {
+        Scrobble::new("Hooverphonic".into(), track.into(), when)
+    }
+
+    #[test]
+    fn test_queue_roundtrip() {
+        let path = std::env::temp_dir().join(format!("queue-{}.toml", std::process::id()));
+        let now = OffsetDateTime::from_unix_timestamp(1689478983).unwrap();
+
+        let mut queue = ScrobbleQueue::default();
+        queue.extend(&[
+            make_scrobble("Eden", now),
+            make_scrobble("Inhaler", now - 5.minutes()),
+            make_scrobble("Cinderella", now - 1.minutes()),
+        ]);
+        queue.save(&path).unwrap();
+
+        let mut loaded = ScrobbleQueue::load(&path).unwrap();
+        let titles: Vec<&str> = loaded.scrobbles.iter().map(|s| s.track.as_str()).collect();
+        assert_eq!(titles, vec!["Inhaler", "Cinderella", "Eden"]);
+        assert_eq!(loaded.scrobbles[2].when, now);
+
+        assert!(loaded.remove(&[4]).is_err());
+        loaded.remove(&[1, 3]).unwrap();
+        assert_eq!(loaded.scrobbles.len(), 1);
+        assert_eq!(loaded.scrobbles[0].track, "Cinderella");
+
+        loaded.scrobbles.clear();
+        loaded.save(&path).unwrap();
+        assert!(!path.exists());
+    }
+}
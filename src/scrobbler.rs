@@ -1,117 +1,1097 @@
-use crate::auth::load_auth_config;
-use crate::lastfmapi::{Album, ApiError, LastfmApi, LastfmApiBuilder};
+use crate::audiofiles::{group_albums, read_audio_files};
+use crate::auth::{for_each_profile, load_auth_config, Profile};
+use crate::cue::parse_cue;
+use crate::import::{parse_record, read_records, ColumnMapping, ImportFormat};
+use crate::lastfmapi::{
+    Album, ApiError, DurationSource, LastfmApi, LastfmApiBuilder, Scrobble, ScrobbleStatus,
+    TrackInfo, DEFAULT_TRACK_DURATION,
+};
+use crate::limits::{check_timestamp, fit_timeline, LimitPolicy};
+use crate::musicbrainz::{MusicBrainzApi, MUSICBRAINZ_HOST};
+use crate::playlist::parse_playlist;
+use crate::prompt::{choose, confirm, is_interactive};
+use crate::queue::{enqueue, list_queue, queue_file, ScrobbleQueue};
+use crate::scrobblerlog::{finish_log, parse_scrobbler_log, AfterLog};
+use crate::selection::TrackSelection;
 use crate::utils::now_local;
 use anyhow::{anyhow, Context};
-use log::{debug, info, warn};
-use time::ext::NumericalDuration;
+use log::{debug, error, info, warn};
+use std::fs;
+use std::path::PathBuf;
 use time::macros::format_description;
-use time::Duration;
+use time::{Duration, OffsetDateTime};
 use url::Url;
 
+/// When the listening happened
+#[derive(Debug, Clone, Copy)]
+pub enum Timing {
+    /// Listening finished given time ago
+    Ago(Duration),
+    /// Listening started at given moment
+    StartedAt(OffsetDateTime),
+    /// Listening finished at given moment
+    FinishedAt(OffsetDateTime),
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Timing::Ago(Duration::ZERO)
+    }
+}
+
+/// Common scrobbling options
+#[derive(Debug, Clone, Default)]
+pub struct ScrobbleOptions {
+    /// Dry run mode (no writes done)
+    pub dryrun: bool,
+    /// Scrobble even tracks which look already scrobbled
+    pub force: bool,
+    /// When the listening happened
+    pub timing: Timing,
+    /// What to do with timestamps outside of Last.fm limits
+    pub limits: LimitPolicy,
+    /// Do not ask questions, take the default answer
+    pub yes: bool,
+    /// Apply Last.fm corrections of artist and track names without asking
+    pub autocorrect: bool,
+}
+
+/// Last.fm accounts to scrobble to
+pub struct Accounts {
+    profiles: Vec<Profile>,
+    apis: Vec<LastfmApi>,
+    /// Profiles which auth config cannot be loaded
+    skipped: usize,
+}
+
+impl Accounts {
+    /// Load auth config of every profile before anything is sent, skipping broken ones
+    pub fn connect(profiles: &[Profile]) -> Result<Self, anyhow::Error> {
+        if profiles.is_empty() {
+            anyhow::bail!("No authenticated profiles, run `auth` first");
+        }
+        let mut accounts = Self {
+            profiles: Vec::with_capacity(profiles.len()),
+            apis: Vec::with_capacity(profiles.len()),
+            skipped: 0,
+        };
+        for profile in profiles {
+            match load_auth_config(profile) {
+                Ok(auth_config) => {
+                    accounts.profiles.push(profile.clone());
+                    accounts
+                        .apis
+                        .push(LastfmApiBuilder::new(auth_config).build());
+                }
+                Err(e) if profiles.len() == 1 => return Err(e),
+                // E.g. keyring secrets are gone after reboot, other profiles still work
+                Err(e) => {
+                    error!("Skipping profile {}: {:#}", profile.name(), e);
+                    accounts.skipped += 1;
+                }
+            }
+        }
+        if accounts.apis.is_empty() {
+            anyhow::bail!("No profile can be used");
+        }
+        Ok(accounts)
+    }
+
+    /// API for lookups which are the same for every account
+    fn lookup(&self) -> &LastfmApi {
+        &self.apis[0]
+    }
+
+    /// Run an action for every account, a failure of one account does not stop others.
+    /// Fails if some profiles were skipped, e.g. so that a scrobbler log is kept for them.
+    pub fn for_each(
+        &self,
+        action: impl FnMut(&Profile, &LastfmApi) -> Result<(), anyhow::Error>,
+    ) -> Result<(), anyhow::Error> {
+        self.for_each_loaded(action)?;
+        if self.skipped > 0 {
+            anyhow::bail!(
+                "Skipped {} of {} profiles",
+                self.skipped,
+                self.skipped + self.profiles.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// Run an action for every account which could be loaded
+    fn for_each_loaded(
+        &self,
+        mut action: impl FnMut(&Profile, &LastfmApi) -> Result<(), anyhow::Error>,
+    ) -> Result<(), anyhow::Error> {
+        for_each_profile(&self.profiles, |idx, profile| {
+            action(profile, &self.apis[idx])
+        })
+    }
+}
+
+/// Gap between consecutive tracks
+const TRACK_GAP: Duration = Duration::seconds(5);
+
+/// Scrobbles of the same track closer than this are duplicates
+const DUPLICATE_WINDOW: Duration = Duration::minutes(15);
+
+/// Compute start times of tracks with given durations played one after another
+fn timeline(durations: &[i64], timing: Timing, now: OffsetDateTime) -> Vec<OffsetDateTime> {
+    let total_len = Duration::seconds(durations.iter().sum())
+        + (durations.len().saturating_sub(1) as i32) * TRACK_GAP;
+    let mut start_time = match timing {
+        Timing::Ago(offset) => now - offset - total_len,
+        Timing::StartedAt(start) => start,
+        Timing::FinishedAt(finish) => finish - total_len,
+    };
+    durations
+        .iter()
+        .map(|&duration| {
+            let track_start = start_time;
+            start_time += Duration::seconds(duration) + TRACK_GAP;
+            track_start
+        })
+        .collect()
+}
+
+/// Report timestamps outside of Last.fm limits and fix them if allowed
+fn check_timeline(
+    times: Vec<OffsetDateTime>,
+    now: OffsetDateTime,
+    options: &ScrobbleOptions,
+) -> Result<Vec<OffsetDateTime>, anyhow::Error> {
+    for (idx, when) in times.iter().enumerate() {
+        if let Some(violation) = check_timestamp(*when, now) {
+            warn!(
+                "Timestamp #{} {} {}",
+                idx + 1,
+                when.format(format_description!(
+                    "[year]-[month]-[day] [hour]:[minute]:[second]"
+                ))?,
+                violation
+            );
+        }
+    }
+    // Show the problem in a preview without failing
+    if options.dryrun && options.limits == LimitPolicy::Refuse {
+        return Ok(times);
+    }
+    fit_timeline(&times, now, options.limits)
+}
+
+/// Find planned scrobbles which are already in recent scrobbles
+fn find_duplicates(planned: &[Scrobble], recent: &[Scrobble]) -> Vec<bool> {
+    planned
+        .iter()
+        .map(|scrobble| {
+            recent.iter().any(|past| {
+                past.artist.to_lowercase() == scrobble.artist.to_lowercase()
+                    && past.track.to_lowercase() == scrobble.track.to_lowercase()
+                    && (past.when - scrobble.when).abs() < DUPLICATE_WINDOW
+            })
+        })
+        .collect()
+}
+
+/// Warn about already scrobbled tracks and drop them unless forced
+fn skip_duplicates(
+    api: &LastfmApi,
+    scrobbles: Vec<Scrobble>,
+    options: &ScrobbleOptions,
+) -> Result<Vec<Scrobble>, anyhow::Error> {
+    if options.force {
+        debug!("Not checking for duplicates because of --force");
+        return Ok(scrobbles);
+    }
+    let (Some(first), Some(last)) = (
+        scrobbles.iter().map(|s| s.when).min(),
+        scrobbles.iter().map(|s| s.when).max(),
+    ) else {
+        return Ok(scrobbles);
+    };
+    let recent = match api.get_username().and_then(|username| {
+        api.get_recent_tracks(&username, first - DUPLICATE_WINDOW, last + DUPLICATE_WINDOW)
+    }) {
+        Ok(recent) => recent,
+        // The check is only advice, e.g. recent listening may be hidden by the user
+        Err(e) => {
+            warn!("Cannot check recent scrobbles for duplicates: {}", e);
+            return Ok(scrobbles);
+        }
+    };
+    let duplicates = find_duplicates(&scrobbles, &recent);
+    if !duplicates.contains(&true) {
+        return Ok(scrobbles);
+    }
+    for (scrobble, _) in scrobbles.iter().zip(&duplicates).filter(|(_, &dup)| dup) {
+        warn!(
+            "Track '{}' of artist '{}' looks already scrobbled around {}",
+            &scrobble.track,
+            &scrobble.artist,
+            scrobble.when.format(format_description!(
+                "[year]-[month]-[day] [hour]:[minute]:[second]"
+            ))?,
+        );
+    }
+    info!("Skipping duplicates, use --force to scrobble them anyway");
+    Ok(scrobbles
+        .into_iter()
+        .zip(duplicates)
+        .filter(|(_, dup)| !dup)
+        .map(|(scrobble, _)| scrobble)
+        .collect())
+}
+
 /// Scrobble all tracks in an album with proper timestamps
 fn scrobble_timeline(
-    api: &LastfmApi,
+    accounts: &Accounts,
     artist: &String,
     album: Album,
-    dryrun: bool,
-    offset: Duration,
+    options: &ScrobbleOptions,
 ) -> Result<(), anyhow::Error> {
     let now = now_local();
-    let album_len: i64 = album.tracks.iter().map(|track| track.duration).sum();
-    let track_gap = 5.seconds();
-
-    let mut start_time =
-        now - Duration::new(album_len, 0) - ((album.tracks.len() - 1) as i16) * track_gap - offset;
-    let mut any_unscrobbled = false;
-    for idx in 0..album.tracks.len() {
-        let track = &album.tracks[idx];
-        start_time += Duration::new(track.duration, 0) + track_gap;
+    let durations: Vec<i64> = album.tracks.iter().map(|track| track.duration).collect();
+    let start_times = check_timeline(timeline(&durations, options.timing, now), now, options)?;
+    let scrobbles = album_scrobbles(artist, &album, start_times, options.dryrun)?;
+    send_album(accounts, scrobbles, options)
+}
+
+/// Send scrobbles of albums failing if some of them were ignored
+fn send_album(
+    accounts: &Accounts,
+    scrobbles: Vec<Scrobble>,
+    options: &ScrobbleOptions,
+) -> Result<(), anyhow::Error> {
+    if deliver(accounts, scrobbles, options, send_scrobbles)? > 0 {
+        Err(anyhow!(format!("Not all tracks scrobbled")))
+    } else {
+        Ok(())
+    }
+}
+
+/// Make and show scrobbles of album tracks starting at given times
+fn album_scrobbles(
+    artist: &String,
+    album: &Album,
+    start_times: Vec<OffsetDateTime>,
+    dryrun: bool,
+) -> Result<Vec<Scrobble>, anyhow::Error> {
+    let multi_disc = album.disc_count() > 1;
+    let mut scrobbles: Vec<Scrobble> = Vec::with_capacity(album.tracks.len());
+    for (idx, (track, start_time)) in album.tracks.iter().zip(start_times).enumerate() {
+        let track_artist = track.artist.as_ref().unwrap_or(artist);
+        let album_artist = track
+            .album_artist
+            .clone()
+            .or_else(|| Some(album.artist.clone()));
+        let number = track.number.map_or(idx + 1, |n| n as usize);
         info!(
-            "{} track #{} '{}' of artist '{}' at {}",
+            "{} {} '{}' of artist '{}' at {} for {} ({})",
             if dryrun { "Previewing" } else { "Scrobbling" },
-            idx + 1,
+            match track.disc.filter(|_| multi_disc) {
+                Some(disc) => format!("disc {} track #{}", disc, number),
+                None => format!("track #{}", number),
+            },
             &track.title,
-            &artist,
-            start_time.format(format_description!("[hour]:[minute]:[second]"))?,
+            track_artist,
+            start_time.format(format_description!(
+                "[year]-[month]-[day] [hour]:[minute]:[second]"
+            ))?,
+            humantime::format_duration(std::time::Duration::from_secs(track.duration as u64)),
+            track.duration_source,
+        );
+        scrobbles.push(Scrobble {
+            album: album.title.clone(),
+            album_artist: album.title.as_ref().and(album_artist),
+            track_number: album.title.as_ref().and(track.number),
+            // Do not report a made up duration
+            duration: Some(track.duration)
+                .filter(|_| track.duration_source != DurationSource::Default),
+            mbid: track.mbid.clone(),
+            ..Scrobble::new(track_artist.clone(), track.title.clone(), start_time)
+        });
+    }
+    Ok(scrobbles)
+}
+
+/// Skip duplicates and send scrobbles to every account, returns a number of ignored scrobbles
+fn deliver(
+    accounts: &Accounts,
+    scrobbles: Vec<Scrobble>,
+    options: &ScrobbleOptions,
+    send: fn(&Profile, &LastfmApi, &[Scrobble]) -> Result<usize, anyhow::Error>,
+) -> Result<usize, anyhow::Error> {
+    let mut ignored = 0;
+    accounts.for_each(|profile, api| {
+        // Every account has its own recent scrobbles
+        let scrobbles = skip_duplicates(api, scrobbles.clone(), options)?;
+        if !options.dryrun && !scrobbles.is_empty() {
+            ignored += send(profile, api, &scrobbles)?;
+        }
+        Ok(())
+    })?;
+    Ok(ignored)
+}
+
+/// Send scrobbles queueing unsent ones on transient failures, returns a number of ignored scrobbles
+fn send_scrobbles(
+    profile: &Profile,
+    api: &LastfmApi,
+    scrobbles: &[Scrobble],
+) -> Result<usize, anyhow::Error> {
+    let outcome = api.scrobble_batch(scrobbles);
+    let mut ignored = 0;
+    for (scrobble, status) in scrobbles.iter().zip(&outcome.statuses) {
+        if let ScrobbleStatus::Ignored(reason) = status {
+            warn!(
+                "Track '{}' not scrobbled due to: {}",
+                scrobble.track, reason
+            );
+            ignored += 1;
+        }
+    }
+    let sent = outcome.sent();
+    if let Some(e) = outcome.error {
+        if sent > 0 {
+            info!(
+                "Sent {} of {} scrobbles before the failure",
+                sent,
+                scrobbles.len()
+            );
+        }
+        // Scrobbles accepted by Last.fm must not be sent again
+        queue_on_error(profile, &scrobbles[sent..], e)?;
+    }
+    Ok(ignored)
+}
+
+/// Scrobble listened tracks from a portable player log
+pub fn scrobble_log(
+    accounts: &Accounts,
+    file: PathBuf,
+    options: &ScrobbleOptions,
+    after: AfterLog,
+) -> Result<(), anyhow::Error> {
+    let content = fs::read_to_string(&file)
+        .with_context(|| format!("Cannot read log file {}", file.display()))?;
+    let entries = parse_scrobbler_log(&content)?;
+    let total = entries.len();
+    let now = now_local();
+
+    let mut scrobbles: Vec<Scrobble> = Vec::with_capacity(total);
+    for entry in entries {
+        let scrobble = entry.scrobble;
+        let when = scrobble.when.format(format_description!(
+            "[year]-[month]-[day] [hour]:[minute]:[second]"
+        ))?;
+        if !entry.listened {
+            debug!("Skipped track '{}' at {}", &scrobble.track, when);
+            continue;
+        }
+        // Last.fm would ignore these anyway
+        if let Some(violation) = check_timestamp(scrobble.when, now) {
+            warn!(
+                "Ignoring track '{}' of artist '{}': timestamp {} {}",
+                &scrobble.track, &scrobble.artist, when, violation
+            );
+            continue;
+        }
+        info!(
+            "{} track '{}' of artist '{}' at {}",
+            if options.dryrun {
+                "Previewing"
+            } else {
+                "Scrobbling"
+            },
+            &scrobble.track,
+            &scrobble.artist,
+            when,
         );
-        if !dryrun {
-            match api.scrobble(artist.clone(), track.title.clone(), start_time) {
-                Ok(_) => {}
-                Err(ApiError::Unscrobbled(reason)) => {
-                    warn!("Not scrobbled due to: {}", reason);
-                    any_unscrobbled = true;
+        scrobbles.push(scrobble);
+    }
+    info!(
+        "Found {} listened tracks of {} log entries",
+        scrobbles.len(),
+        total
+    );
+
+    let count = scrobbles.len();
+    // The log is kept unless every account got the scrobbles
+    let ignored = deliver(accounts, scrobbles, options, send_scrobbles)?;
+    if options.dryrun {
+        return Ok(());
+    }
+    if ignored > 0 {
+        warn!("{} of {} tracks not scrobbled", ignored, count);
+    }
+    finish_log(&file, &content, after)
+}
+
+/// Import scrobbles from a CSV/JSON listening log, reporting invalid rows
+pub fn import_file(
+    accounts: &Accounts,
+    file: PathBuf,
+    format: ImportFormat,
+    delimiter: char,
+    mapping: &ColumnMapping,
+    options: &ScrobbleOptions,
+) -> Result<(), anyhow::Error> {
+    let content = fs::read_to_string(&file)
+        .with_context(|| format!("Cannot read listening log {}", file.display()))?;
+    let records = read_records(&content, format, delimiter)?;
+    let now = now_local();
+
+    let mut scrobbles: Vec<Scrobble> = Vec::with_capacity(records.len());
+    let mut errors = 0;
+    for (idx, record) in records.iter().enumerate() {
+        match parse_record(record, mapping, now) {
+            Ok(scrobble) => {
+                info!(
+                    "{} row {}: track '{}' of artist '{}'{} at {}",
+                    if options.dryrun {
+                        "Previewing"
+                    } else {
+                        "Scrobbling"
+                    },
+                    idx + 1,
+                    &scrobble.track,
+                    &scrobble.artist,
+                    scrobble
+                        .album
+                        .as_ref()
+                        .map_or(String::new(), |album| format!(" from album '{}'", album)),
+                    scrobble.when.format(format_description!(
+                        "[year]-[month]-[day] [hour]:[minute]:[second]"
+                    ))?,
+                );
+                scrobbles.push(scrobble);
+            }
+            Err(e) => {
+                warn!("Row {}: {:#}", idx + 1, e);
+                errors += 1;
+            }
+        }
+    }
+    info!(
+        "Found {} valid rows and {} invalid rows of {}",
+        scrobbles.len(),
+        errors,
+        records.len()
+    );
+    if scrobbles.is_empty() {
+        anyhow::bail!("Nothing to import");
+    }
+
+    let count = scrobbles.len();
+    let ignored = deliver(accounts, scrobbles, options, send_scrobbles)?;
+    if ignored > 0 {
+        warn!("{} of {} tracks not scrobbled", ignored, count);
+    }
+    Ok(())
+}
+
+/// Find durations of tracks unknown to the release with track.getInfo,
+/// then MusicBrainz, then fall back to a default
+fn fill_durations(
+    api: &LastfmApi,
+    musicbrainz: &MusicBrainzApi,
+    album: &mut Album,
+    default_duration: Option<std::time::Duration>,
+) {
+    let default_duration = default_duration.map_or(DEFAULT_TRACK_DURATION, |d| d.as_secs() as i64);
+    for track in album
+        .tracks
+        .iter_mut()
+        .filter(|track| track.duration_source == DurationSource::Default)
+    {
+        let artist = track.artist.as_ref().unwrap_or(&album.artist);
+        let mut mbid = track.mbid.clone();
+        match api.get_track_info(artist, &track.title) {
+            Ok(info) => {
+                if let Some(duration) = info.duration {
+                    track.duration = duration;
+                    track.duration_source = DurationSource::TrackInfo;
+                    continue;
+                }
+                mbid = mbid.or(info.mbid);
+            }
+            Err(e) => debug!("No track info for '{}': {}", &track.title, e),
+        }
+        if let Some(mbid) = mbid {
+            match musicbrainz.get_recording_length(&mbid) {
+                Ok(Some(duration)) => {
+                    track.duration = duration;
+                    track.duration_source = DurationSource::MusicBrainz;
+                    continue;
                 }
-                Err(e) => return Err(e.into()),
-            };
+                Ok(None) => debug!("No length of MusicBrainz recording {}", mbid),
+                Err(e) => debug!("Cannot get MusicBrainz recording {}: {:#}", mbid, e),
+            }
         }
+        warn!(
+            "Duration of track '{}' is unknown, assuming {}",
+            &track.title,
+            humantime::format_duration(std::time::Duration::from_secs(default_duration as u64))
+        );
+        track.duration = default_duration;
     }
+}
 
-    if any_unscrobbled {
-        Err(anyhow!(format!("Not all tracks scrobbled")))
-    } else {
-        Ok(())
+/// Keep only selected tracks of an album
+fn select_tracks(album: &mut Album, selection: &TrackSelection) -> Result<(), anyhow::Error> {
+    if album.disc_count() > 1 {
+        info!("Album has {} discs", album.disc_count());
+    }
+    if let Some(disc) = selection.disc {
+        album.tracks.retain(|track| track.disc.unwrap_or(1) == disc);
+        if album.tracks.is_empty() {
+            anyhow::bail!("No disc {} in album", disc);
+        }
+    }
+    if !selection.is_all() {
+        let total = album.tracks.len();
+        album.tracks = selection.apply(std::mem::take(&mut album.tracks));
+        info!("Selected {} of {} tracks", album.tracks.len(), total);
+    }
+    if album.tracks.is_empty() {
+        anyhow::bail!("No tracks selected");
+    }
+    Ok(())
+}
+
+/// Scrobble tracks of a M3U/M3U8/PLS playlist
+pub fn scrobble_playlist(
+    accounts: &Accounts,
+    file: PathBuf,
+    default_duration: Option<std::time::Duration>,
+    options: &ScrobbleOptions,
+    selection: &TrackSelection,
+) -> Result<(), anyhow::Error> {
+    let content =
+        fs::read(&file).with_context(|| format!("Cannot read playlist {}", file.display()))?;
+    let tracks = parse_playlist(&String::from_utf8_lossy(&content))?;
+    info!("Found {} tracks in playlist", tracks.len());
+    // Every track has its own artist and there is no album
+    let mut playlist = Album {
+        title: None,
+        artist: String::new(),
+        tracks,
+        url: None,
+        mbid: None,
+    };
+    select_tracks(&mut playlist, selection)?;
+
+    let musicbrainz = MusicBrainzApi::new(MUSICBRAINZ_HOST.to_string());
+    fill_durations(
+        accounts.lookup(),
+        &musicbrainz,
+        &mut playlist,
+        default_duration,
+    );
+    scrobble_timeline(accounts, &String::new(), playlist, options)
+}
+
+/// Scrobble an album described by a CUE sheet
+pub fn scrobble_cue(
+    accounts: &Accounts,
+    file: PathBuf,
+    last_duration: Option<std::time::Duration>,
+    options: &ScrobbleOptions,
+    selection: &TrackSelection,
+) -> Result<(), anyhow::Error> {
+    // CUE sheets are often not in UTF-8
+    let content =
+        fs::read(&file).with_context(|| format!("Cannot read CUE sheet {}", file.display()))?;
+    let last_duration = last_duration.map_or(DEFAULT_TRACK_DURATION, |d| d.as_secs() as i64);
+    let mut album = parse_cue(&String::from_utf8_lossy(&content), last_duration)?;
+    info!(
+        "Album name {} of artist {} with {} tracks",
+        album.title.as_deref().unwrap_or_default(),
+        &album.artist,
+        album.tracks.len()
+    );
+    select_tracks(&mut album, selection)?;
+
+    let artist = album.artist.clone();
+    scrobble_timeline(accounts, &artist, album, options)
+}
+
+/// Scrobble tracks of local audio files using their tags and durations
+pub fn scrobble_files(
+    accounts: &Accounts,
+    paths: Vec<PathBuf>,
+    options: &ScrobbleOptions,
+    selection: &TrackSelection,
+) -> Result<(), anyhow::Error> {
+    let file_tracks = read_audio_files(&paths)?;
+    info!("Read tags of {} audio files", file_tracks.len());
+    let mut albums = group_albums(file_tracks)?;
+    if albums.len() > 1 && !selection.is_all() {
+        anyhow::bail!("Track selection is only supported for a single album");
+    }
+    for album in albums.iter_mut() {
+        info!(
+            "Album name {} of artist {} with {} tracks",
+            album.title.as_deref().unwrap_or_default(),
+            &album.artist,
+            album.tracks.len()
+        );
+        select_tracks(album, selection)?;
+    }
+
+    // Albums are played one after another, every timestamp is checked before sending anything
+    let now = now_local();
+    let durations: Vec<i64> = albums
+        .iter()
+        .flat_map(|album| album.tracks.iter().map(|track| track.duration))
+        .collect();
+    let mut start_times =
+        check_timeline(timeline(&durations, options.timing, now), now, options)?.into_iter();
+    let mut scrobbles = Vec::with_capacity(durations.len());
+    for album in &albums {
+        let album_times = start_times.by_ref().take(album.tracks.len()).collect();
+        scrobbles.extend(album_scrobbles(
+            &album.artist,
+            album,
+            album_times,
+            options.dryrun,
+        )?);
+    }
+    send_album(accounts, scrobbles, options)
+}
+
+/// Decide whether to use a name correction suggested by Last.fm
+fn accept_correction(
+    given: &str,
+    corrected: &str,
+    options: &ScrobbleOptions,
+) -> anyhow::Result<bool> {
+    if options.autocorrect {
+        info!("Correcting '{}' to '{}'", given, corrected);
+        return Ok(true);
+    }
+    if options.yes || !is_interactive() {
+        warn!(
+            "Last.fm suggests '{}' instead of '{}', use --autocorrect to apply it",
+            corrected, given
+        );
+        return Ok(false);
+    }
+    let accepted = confirm(&format!("Use '{}' instead of '{}'?", corrected, given))?;
+    if accepted {
+        info!("Correcting '{}' to '{}'", given, corrected);
+    }
+    Ok(accepted)
+}
+
+/// Artist name as corrected by Last.fm if accepted
+fn correct_artist(
+    api: &LastfmApi,
+    artist: String,
+    options: &ScrobbleOptions,
+) -> Result<String, anyhow::Error> {
+    // A correction is only advice, the given name is scrobbled if it cannot be checked
+    match api.get_artist_correction(&artist) {
+        Ok(Some(corrected)) if accept_correction(&artist, &corrected, options)? => Ok(corrected),
+        Ok(_) => Ok(artist),
+        Err(e) => {
+            warn!("Cannot check artist name: {}", e);
+            Ok(artist)
+        }
+    }
+}
+
+/// Artist and track names as corrected by Last.fm if accepted
+fn correct_track(
+    api: &LastfmApi,
+    artist: String,
+    track: String,
+    options: &ScrobbleOptions,
+) -> Result<(String, String), anyhow::Error> {
+    match api.get_track_correction(&artist, &track) {
+        Ok(Some((corrected_artist, corrected_track)))
+            if accept_correction(
+                &format!("{} - {}", artist, track),
+                &format!("{} - {}", corrected_artist, corrected_track),
+                options,
+            )? =>
+        {
+            Ok((corrected_artist, corrected_track))
+        }
+        Ok(_) => Ok((artist, track)),
+        Err(e) => {
+            warn!("Cannot check track name: {}", e);
+            Ok((artist, track))
+        }
     }
 }
 
 /// Scrobble a whole album of an artist
 pub fn scrobble_album(
+    accounts: &Accounts,
     artist: String,
     album: String,
-    dryrun: bool,
-    start: Option<Duration>,
+    search: bool,
+    default_duration: Option<std::time::Duration>,
+    options: &ScrobbleOptions,
+    selection: &TrackSelection,
 ) -> Result<(), anyhow::Error> {
-    let auth_config = load_auth_config()?;
-    let api = LastfmApiBuilder::new(auth_config).build();
-    // When the track scrobbled - subset offset from current time
-    let offset = start.map_or(Duration::ZERO, |v| v);
-    debug!("Scrobble offset {:?}", offset);
-
-    match api.get_album_tracks(artist.clone(), album.clone()) {
-        Ok(album_info) => {
-            if album_info.title != album {
-                warn!(
-                    "Album name {} differs from given {}",
-                    &album_info.title, &album
-                );
-            }
-            info!("Album name {}", &album_info.title);
-            if let Some(album_url) = &album_info.url {
-                info!("Album url {}", &album_url);
+    let api = accounts.lookup();
+    debug!("Scrobble timing {:?}", options.timing);
+    let artist = correct_artist(api, artist, options)?;
+
+    let mut album_info = if search {
+        pick_album(api, &artist, &album, options)?
+    } else {
+        let album_info = api.get_album_tracks(artist.clone(), album.clone())?;
+        let differs = album_info
+            .title
+            .as_deref()
+            .is_none_or(|title| title.to_lowercase() != album.to_lowercase());
+        if differs {
+            warn!(
+                "Album name {} differs from given {}",
+                album_info.title.as_deref().unwrap_or_default(),
+                &album
+            );
+        }
+        // Without a terminal the found album is used as before
+        if differs && !options.yes && is_interactive() {
+            pick_album(api, &artist, &album, options)?
+        } else {
+            album_info
+        }
+    };
+    info!(
+        "Album name {}",
+        album_info.title.as_deref().unwrap_or_default()
+    );
+    if let Some(album_url) = &album_info.url {
+        info!("Album url {}", &album_url);
+    }
+    if let Some(album_mbid) = &album_info.mbid {
+        debug!("Album mbid {}", &album_mbid);
+    }
+    select_tracks(&mut album_info, selection)?;
+    let musicbrainz = MusicBrainzApi::new(MUSICBRAINZ_HOST.to_string());
+    fill_durations(api, &musicbrainz, &mut album_info, default_duration);
+    scrobble_timeline(accounts, &artist, album_info, options)
+}
+
+/// How many search results are offered with their track lists
+const SEARCH_CANDIDATES: usize = 5;
+
+/// How many search results are looked up to find candidates with tracks
+const MAX_SEARCH_LOOKUPS: usize = 10;
+
+/// Search albums by name and let the user choose one
+fn pick_album(
+    api: &LastfmApi,
+    artist: &str,
+    album: &str,
+    options: &ScrobbleOptions,
+) -> Result<Album, anyhow::Error> {
+    let matches = api.search_album(album)?;
+    // Prefer albums of the given artist
+    let (same_artist, others): (Vec<_>, Vec<_>) = matches
+        .into_iter()
+        .partition(|found| found.artist.to_lowercase() == artist.to_lowercase());
+    let matches = if same_artist.is_empty() {
+        others
+    } else {
+        same_artist
+    };
+
+    let mut candidates: Vec<Album> = Vec::new();
+    for found in matches.into_iter().take(MAX_SEARCH_LOOKUPS) {
+        if candidates.len() >= SEARCH_CANDIDATES {
+            break;
+        }
+        match api.get_album_tracks(found.artist.clone(), found.title.clone()) {
+            Ok(candidate) if !candidate.tracks.is_empty() => candidates.push(candidate),
+            Ok(_) => debug!("Album '{}' has no tracks", &found.title),
+            // Retries are already exhausted, next lookups would fail the same way
+            Err(e) if e.is_retryable() => {
+                warn!("Cannot get album '{}': {}", &found.title, e);
+                break;
             }
-            scrobble_timeline(&api, &artist, album_info, dryrun, offset)?;
-            Ok(())
+            Err(e) => debug!("Cannot get album '{}': {}", &found.title, e),
+        }
+    }
+    if candidates.is_empty() {
+        anyhow::bail!("No albums found for '{}'", album);
+    }
+
+    for (idx, candidate) in candidates.iter().enumerate() {
+        let length: i64 = candidate.tracks.iter().map(|track| track.duration).sum();
+        info!(
+            "#{} '{}' of artist '{}': {} tracks, {}",
+            idx + 1,
+            candidate.title.as_deref().unwrap_or_default(),
+            &candidate.artist,
+            candidate.tracks.len(),
+            humantime::format_duration(std::time::Duration::from_secs(length as u64))
+        );
+    }
+    let choice = if options.yes {
+        0
+    } else if !is_interactive() {
+        warn!("Cannot ask without a terminal, taking album #1");
+        0
+    } else {
+        choose(candidates.len())?.context("No album chosen")?
+    };
+    Ok(candidates.swap_remove(choice))
+}
+
+/// Duration of a track in seconds as known to Last.fm or a default
+fn track_duration(api: &LastfmApi, artist: &str, track: &str) -> i64 {
+    match api.get_track_info(artist, track) {
+        Ok(TrackInfo {
+            duration: Some(duration),
+            ..
+        }) => duration,
+        Ok(_) => {
+            warn!(
+                "Duration of track '{}' is unknown, assuming {}",
+                track,
+                humantime::format_duration(std::time::Duration::from_secs(
+                    DEFAULT_TRACK_DURATION as u64
+                ))
+            );
+            DEFAULT_TRACK_DURATION
+        }
+        Err(e) => {
+            warn!("Cannot get duration of track '{}': {}", track, e);
+            DEFAULT_TRACK_DURATION
         }
-        Err(e) => Err(e.into()),
     }
 }
 
 /// Scrobble a track of an artist
 pub fn scrobble_track(
+    accounts: &Accounts,
     artist: String,
     track: String,
-    _dryrun: bool,
-    start: Option<Duration>,
+    options: &ScrobbleOptions,
 ) -> Result<(), anyhow::Error> {
-    let auth_config = load_auth_config()?;
-    let api = LastfmApiBuilder::new(auth_config).build();
-    // When the track scrobbled - subset offset from current time
-    let offset = start.map_or(Duration::ZERO, |v| v);
-    let when = now_local() - offset;
-    match api.scrobble(artist, track, when) {
-        Ok(()) => Ok(()),
-        Err(ApiError::Unscrobbled(reason)) => {
-            warn!("Not scrobbled due to: {}", reason);
+    let api = accounts.lookup();
+    let (artist, track) = correct_track(api, artist, track, options)?;
+    let now = now_local();
+    let when = match options.timing {
+        // Time ago is a point in time as the track duration is not given
+        Timing::Ago(offset) => now - offset,
+        Timing::StartedAt(start) => start,
+        Timing::FinishedAt(finish) => {
+            finish - Duration::seconds(track_duration(api, &artist, &track))
+        }
+    };
+    let when = check_timeline(vec![when], now, options)?[0];
+    info!(
+        "{} track '{}' of artist '{}' at {}",
+        if options.dryrun {
+            "Previewing"
+        } else {
+            "Scrobbling"
+        },
+        &track,
+        &artist,
+        when.format(format_description!(
+            "[year]-[month]-[day] [hour]:[minute]:[second]"
+        ))?,
+    );
+    let scrobble = Scrobble::new(artist, track, when);
+    deliver(accounts, vec![scrobble], options, send_scrobble)?;
+    Ok(())
+}
+
+/// Send scrobbles one at a time without indexed params, returns a number of ignored scrobbles
+fn send_scrobble(
+    profile: &Profile,
+    api: &LastfmApi,
+    scrobbles: &[Scrobble],
+) -> Result<usize, anyhow::Error> {
+    let mut ignored = 0;
+    for (idx, scrobble) in scrobbles.iter().enumerate() {
+        match api.scrobble(scrobble) {
+            Ok(()) => {}
+            Err(ApiError::Unscrobbled(reason)) => {
+                warn!("Not scrobbled due to: {}", reason);
+                ignored += 1;
+            }
+            Err(e) => {
+                queue_on_error(profile, &scrobbles[idx..], e)?;
+                break;
+            }
+        }
+    }
+    Ok(ignored)
+}
+
+/// Mark a track as playing now and optionally scrobble it when it ends
+pub fn now_playing(
+    accounts: &Accounts,
+    artist: String,
+    track: String,
+    album: Option<String>,
+    duration: Option<std::time::Duration>,
+    then_scrobble: bool,
+    dryrun: bool,
+) -> Result<(), anyhow::Error> {
+    let duration_sec = duration.map(|d| d.as_secs() as i64);
+    let started = now_local();
+
+    info!(
+        "{} now playing '{}' of artist '{}'",
+        if dryrun { "Previewing" } else { "Updating" },
+        &track,
+        &artist
+    );
+    let update = |_: &Profile, api: &LastfmApi| {
+        match api.update_now_playing(artist.clone(), track.clone(), album.clone(), duration_sec) {
+            Ok(()) => {}
+            Err(ApiError::Unscrobbled(reason)) => {
+                warn!("Now playing ignored due to: {}", reason);
+            }
+            Err(e) => return Err(e.into()),
+        }
+        Ok(())
+    };
+    if !then_scrobble {
+        return if dryrun {
+            Ok(())
+        } else {
+            accounts.for_each(update)
+        };
+    }
+    if !dryrun {
+        // Skipped profiles are reported after scrobbling
+        accounts.for_each_loaded(update)?;
+    }
+
+    let duration = duration.context("Track duration is required to scrobble it")?;
+    info!(
+        "Waiting {} for the track to finish",
+        humantime::format_duration(duration)
+    );
+    if !dryrun {
+        std::thread::sleep(duration);
+    }
+
+    let scrobble = Scrobble {
+        album,
+        duration: duration_sec,
+        ..Scrobble::new(artist, track, started)
+    };
+    info!(
+        "{} track '{}' of artist '{}' at {}",
+        if dryrun { "Previewing" } else { "Scrobbling" },
+        &scrobble.track,
+        &scrobble.artist,
+        started.format(format_description!("[hour]:[minute]:[second]"))?,
+    );
+    if dryrun {
+        return Ok(());
+    }
+    // Waited once, every account gets the same scrobble
+    accounts.for_each(|profile, api| {
+        send_scrobbles(profile, api, std::slice::from_ref(&scrobble))?;
+        Ok(())
+    })
+}
+
+/// Save scrobbles to the offline queue if the error is worth retrying later
+fn queue_on_error(
+    profile: &Profile,
+    scrobbles: &[Scrobble],
+    err: ApiError,
+) -> Result<(), anyhow::Error> {
+    if !err.is_retryable() {
+        return Err(err.into());
+    }
+    warn!("Cannot scrobble now: {}", err);
+    enqueue(profile, scrobbles)?;
+    info!("Run `flush` later to send queued scrobbles");
+    Ok(())
+}
+
+/// Send all queued scrobbles of a profile in timestamp order
+pub fn flush_queue(
+    profile: &Profile,
+    api: &LastfmApi,
+    dryrun: bool,
+    limits: LimitPolicy,
+) -> Result<(), anyhow::Error> {
+    let path = queue_file(profile)?;
+    let mut queue = ScrobbleQueue::load(&path)?;
+    if queue.scrobbles.is_empty() {
+        info!("Queue is empty");
+        return Ok(());
+    }
+    info!(
+        "{} {} queued scrobbles",
+        if dryrun { "Previewing" } else { "Flushing" },
+        queue.scrobbles.len()
+    );
+    // Scrobbles may have waited in the queue for too long
+    let now = now_local();
+    let times = queue
+        .scrobbles
+        .iter()
+        .map(|scrobble| scrobble.when)
+        .collect();
+    let options = ScrobbleOptions {
+        dryrun,
+        limits,
+        ..ScrobbleOptions::default()
+    };
+    let times = check_timeline(times, now, &options)
+        .context("Fix queued timestamps or remove them with `queue drop`")?;
+    if dryrun {
+        return list_queue(profile);
+    }
+    for (scrobble, when) in queue.scrobbles.iter_mut().zip(times) {
+        scrobble.when = when;
+    }
+
+    let outcome = api.scrobble_batch(&queue.scrobbles);
+    for (scrobble, status) in queue.scrobbles.iter().zip(&outcome.statuses) {
+        if let ScrobbleStatus::Ignored(reason) = status {
+            warn!(
+                "Dropping track '{}' not scrobbled due to: {}",
+                scrobble.track, reason
+            );
+        }
+    }
+
+    // Keep only scrobbles which were not sent
+    let sent = outcome.sent();
+    queue.scrobbles.drain(..sent);
+    queue.save(&path)?;
+    match outcome.error {
+        None => {
+            info!("Flushed {} scrobbles", sent);
             Ok(())
         }
-        Err(e) => Err(e.into()),
+        Some(e) => {
+            warn!(
+                "Flushed {} scrobbles, {} left in the queue",
+                sent,
+                queue.scrobbles.len()
+            );
+            Err(e.into())
+        }
     }
 }
 
 /// Scrobble a whole album identified by Last.fm webpage URL
 pub fn scrobble_url(
+    accounts: &Accounts,
     url: String,
-    dryrun: bool,
-    start: Option<Duration>,
+    default_duration: Option<std::time::Duration>,
+    options: &ScrobbleOptions,
+    selection: &TrackSelection,
 ) -> Result<(), anyhow::Error> {
     let expected_format = "https://www.last.fm/music/Artist/Album+Name";
 
@@ -134,5 +1114,270 @@ pub fn scrobble_url(
 
     info!("Extracted artist {} and album {}", &artist, &album);
 
-    scrobble_album(artist, album, dryrun, start)
+    scrobble_album(
+        accounts,
+        artist,
+        album,
+        false,
+        default_duration,
+        options,
+        selection,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::auth::AuthConfig;
+    use crate::lastfmapi::Track;
+    use httpmock::prelude::*;
+    use test_log::test;
+    use time::macros::datetime;
+
+    #[test]
+    fn test_timeline() {
+        let now = datetime!(2026-10-16 12:00 UTC);
+        let durations = [100, 200, 300];
+
+        let start_times = timeline(&durations, Timing::default(), now);
+        assert_eq!(start_times[0], datetime!(2026-10-16 11:49:50 UTC));
+        assert_eq!(start_times[2], datetime!(2026-10-16 11:55:00 UTC));
+
+        let start_times = timeline(
+            &durations,
+            Timing::StartedAt(datetime!(2026-10-15 21:30 UTC)),
+            now,
+        );
+        assert_eq!(start_times[0], datetime!(2026-10-15 21:30 UTC));
+        assert_eq!(start_times[1], datetime!(2026-10-15 21:31:45 UTC));
+
+        let start_times = timeline(
+            &durations,
+            Timing::FinishedAt(datetime!(2026-10-15 22:00 UTC)),
+            now,
+        );
+        assert_eq!(start_times[2], datetime!(2026-10-15 21:55 UTC));
+
+        let start_times = timeline(&durations, Timing::Ago(Duration::hours(1)), now);
+        assert_eq!(start_times[2], datetime!(2026-10-16 10:55 UTC));
+    }
+
+    #[test]
+    fn test_corrections() {
+        let server = MockServer::start();
+        let mock_artist = server.mock(|when, then| {
+            when.method(POST)
+                .path("/2.0/")
+                .query_param("method", "artist.getCorrection")
+                .query_param("artist", "Guns and Roses");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(include_str!("data/resp.artistcorrection.json"));
+        });
+        let mock_track = server.mock(|when, then| {
+            when.method(POST)
+                .path("/2.0/")
+                .query_param("method", "track.getCorrection");
+            then.status(400)
+                .header("content-type", "application/json")
+                .body(r#"{"error": 6, "message": "Track not found"}"#);
+        });
+        let api = LastfmApiBuilder::new(AuthConfig {
+            api_key: String::new(),
+            secret_key: String::new(),
+            session_key: String::new(),
+        })
+        .with_api_host(format!("http://{}", server.address()))
+        .build();
+        let options = ScrobbleOptions {
+            autocorrect: true,
+            ..ScrobbleOptions::default()
+        };
+
+        assert_eq!(
+            correct_artist(&api, "Guns and Roses".into(), &options).unwrap(),
+            "Guns N' Roses"
+        );
+        // Without --autocorrect the given name is kept when not asking
+        let no_questions = ScrobbleOptions {
+            yes: true,
+            ..ScrobbleOptions::default()
+        };
+        assert_eq!(
+            correct_artist(&api, "Guns and Roses".into(), &no_questions).unwrap(),
+            "Guns and Roses"
+        );
+        // A failed check scrobbles the given names
+        assert_eq!(
+            correct_track(&api, "Hooverphonic".into(), "Edne".into(), &options).unwrap(),
+            ("Hooverphonic".to_string(), "Edne".to_string())
+        );
+        mock_artist.assert_hits(2);
+        mock_track.assert_hits(1);
+    }
+
+    #[test]
+    fn test_fill_durations() {
+        let server = MockServer::start();
+        let mock_info = server.mock(|when, then| {
+            when.method(POST)
+                .path("/2.0/")
+                .query_param("method", "track.getInfo");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(
+                    r#"{"track": {"name": "Eden", "duration": "0",
+                        "mbid": "7cbdba3e-2b7d-4f85-a05a-5d4bf1b0ab67"}}"#,
+                );
+        });
+        let mock_recording = server.mock(|when, then| {
+            when.method(GET)
+                .path("/ws/2/recording/7cbdba3e-2b7d-4f85-a05a-5d4bf1b0ab67");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(r#"{"title": "Eden", "length": 241466}"#);
+        });
+        let api = LastfmApiBuilder::new(AuthConfig {
+            api_key: String::new(),
+            secret_key: String::new(),
+            session_key: String::new(),
+        })
+        .with_api_host(format!("http://{}", server.address()))
+        .build();
+        let musicbrainz = MusicBrainzApi::new(format!("http://{}", server.address()));
+
+        let track = |title: &str, duration_source| Track {
+            title: title.to_string(),
+            artist: Some("Hooverphonic".to_string()),
+            album_artist: None,
+            duration: DEFAULT_TRACK_DURATION,
+            duration_source,
+            number: None,
+            disc: None,
+            mbid: None,
+        };
+        let mut album = Album {
+            title: None,
+            artist: String::new(),
+            tracks: vec![
+                track("Eden", DurationSource::Default),
+                track("One", DurationSource::Release),
+            ],
+            url: None,
+            mbid: None,
+        };
+        fill_durations(&api, &musicbrainz, &mut album, None);
+        mock_info.assert_hits(1);
+        mock_recording.assert();
+        assert_eq!(album.tracks[0].duration, 241);
+        assert_eq!(album.tracks[0].duration_source, DurationSource::MusicBrainz);
+        assert_eq!(album.tracks[1].duration_source, DurationSource::Release);
+    }
+
+    #[test]
+    fn test_skip_duplicates_failed_lookup() {
+        let server = MockServer::start();
+        let mock_user = server.mock(|when, then| {
+            when.method(POST)
+                .path("/2.0")
+                .x_www_form_urlencoded_tuple("method", "user.getInfo");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(r#"{"user": {"name": "rj"}}"#);
+        });
+        let mock_recent = server.mock(|when, then| {
+            when.method(POST)
+                .path("/2.0/")
+                .query_param("method", "user.getRecentTracks");
+            then.status(403)
+                .header("content-type", "application/json")
+                .body(r#"{"error": 17, "message": "Login: User required to be logged in"}"#);
+        });
+        let api = LastfmApiBuilder::new(AuthConfig {
+            api_key: String::new(),
+            secret_key: String::new(),
+            session_key: String::new(),
+        })
+        .with_api_host(format!("http://{}", server.address()))
+        .build();
+        let when = datetime!(2026-10-16 12:00 UTC);
+        let scrobbles = vec![Scrobble::new("Hooverphonic".into(), "Eden".into(), when)];
+
+        // A failed lookup does not stop scrobbling
+        let kept = skip_duplicates(&api, scrobbles.clone(), &ScrobbleOptions::default()).unwrap();
+        assert_eq!(kept.len(), 1);
+        mock_user.assert();
+        mock_recent.assert();
+
+        // Forced scrobbles are not checked at all
+        let options = ScrobbleOptions {
+            force: true,
+            ..ScrobbleOptions::default()
+        };
+        assert_eq!(skip_duplicates(&api, scrobbles, &options).unwrap().len(), 1);
+        mock_user.assert_hits(1);
+    }
+
+    #[test]
+    fn test_connect_skips_broken_profiles() {
+        let root = std::env::temp_dir().join(format!("accounts-{}", std::process::id()));
+        let profile = |name: &str| Profile::in_dir(Some(name.to_string()), root.join(name));
+        let good = profile("good");
+        fs::create_dir_all(good.dir().unwrap()).unwrap();
+        fs::write(
+            good.dir().unwrap().join("config.toml"),
+            "api_key = \"key\"\nsecret_key = \"secret\"\nsession_key = \"sk\"\n",
+        )
+        .unwrap();
+        // Secrets are gone like from a kernel keyring after reboot
+        let broken = profile("broken");
+        fs::write(
+            broken.dir().unwrap().join("config.toml"),
+            "api_key = \"key\"\n",
+        )
+        .unwrap();
+
+        let accounts = Accounts::connect(&[broken.clone(), good]).unwrap();
+        assert_eq!(accounts.profiles.len(), 1);
+        assert_eq!(accounts.profiles[0].name(), "good");
+        let mut visited = 0;
+        assert!(accounts
+            .for_each(|_, _| {
+                visited += 1;
+                Ok(())
+            })
+            .is_err());
+        assert_eq!(visited, 1);
+
+        assert!(Accounts::connect(&[broken]).is_err());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        let now = datetime!(2026-10-16 12:00 UTC);
+        let planned = vec![
+            Scrobble::new("Hooverphonic".into(), "Eden".into(), now),
+            Scrobble::new(
+                "Hooverphonic".into(),
+                "Inhaler".into(),
+                now + Duration::minutes(5),
+            ),
+            Scrobble::new("Hooverphonic".into(), "Cinderella".into(), now),
+        ];
+        let recent = vec![
+            Scrobble::new(
+                "hooverphonic".into(),
+                "eden".into(),
+                now - Duration::minutes(3),
+            ),
+            Scrobble::new(
+                "Hooverphonic".into(),
+                "Inhaler".into(),
+                now - Duration::hours(2),
+            ),
+        ];
+        assert_eq!(find_duplicates(&planned, &recent), vec![true, false, false]);
+    }
 }
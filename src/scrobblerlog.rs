@@ -0,0 +1,169 @@
+use crate::lastfmapi::Scrobble;
+use crate::utils::assume_local;
+use anyhow::Context;
+use clap::ValueEnum;
+use std::fs;
+use std::path::{Path, PathBuf};
+use time::macros::format_description;
+use time::{OffsetDateTime, PrimitiveDateTime};
+
+/// An entry of Audioscrobbler portable player log
+#[derive(Debug)]
+pub struct LogEntry {
+    pub scrobble: Scrobble,
+    /// Track was listened (L) and not skipped (S)
+    pub listened: bool,
+}
+
+/// What to do with a log file after scrobbling
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum AfterLog {
+    /// Leave the file as is
+    #[default]
+    Keep,
+    /// Remove all entries keeping the header
+    Truncate,
+    /// Rename the file adding a timestamp suffix
+    Archive,
+}
+
+/// Parse Audioscrobbler log format 1.1 used by Rockbox and other portable players
+/// See https://web.archive.org/web/20170107015006/http://www.audioscrobbler.net/wiki/Portable_Player_Logging
+pub fn parse_scrobbler_log(content: &str) -> anyhow::Result<Vec<LogEntry>> {
+    let mut lines = content.lines().enumerate();
+    match lines.next() {
+        Some((_, header)) if header.trim_end().starts_with("#AUDIOSCROBBLER/") => {}
+        _ => anyhow::bail!("Not an Audioscrobbler log, expected #AUDIOSCROBBLER header"),
+    }
+
+    let mut utc = false;
+    let mut entries = Vec::new();
+    for (idx, line) in lines {
+        let line = line.trim_end_matches(['\r', '\n']);
+        if let Some(header) = line.strip_prefix('#') {
+            if let Some(tz) = header.strip_prefix("TZ/") {
+                utc = tz.trim() == "UTC";
+            }
+            continue;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = parse_entry(line, utc).with_context(|| format!("Line {}", idx + 1))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+fn parse_entry(line: &str, utc: bool) -> anyhow::Result<LogEntry> {
+    // artist, album, title, tracknum, length, rating, timestamp, optional mbid
+    let fields: Vec<&str> = line.split('\t').collect();
+    if fields.len() < 7 {
+        anyhow::bail!(
+            "Expected at least 7 tab-separated fields, got {}",
+            fields.len()
+        );
+    }
+    let non_empty = |s: &str| Some(s.trim().to_string()).filter(|s| !s.is_empty());
+
+    let artist = non_empty(fields[0]).context("Empty artist")?;
+    let track = non_empty(fields[2]).context("Empty title")?;
+    let listened = match fields[5].trim() {
+        "L" => true,
+        "S" => false,
+        rating => anyhow::bail!("Unknown rating '{}'", rating),
+    };
+    let timestamp: i64 = fields[6].trim().parse().context("Wrong timestamp")?;
+    let when = OffsetDateTime::from_unix_timestamp(timestamp)?;
+    // Players without a clock in UTC write local time as if it was UTC
+    let when = if utc {
+        when
+    } else {
+        assume_local(PrimitiveDateTime::new(when.date(), when.time()))
+    };
+
+    Ok(LogEntry {
+        scrobble: Scrobble {
+            album: non_empty(fields[1]),
+            track_number: fields[3].trim().parse().ok(),
+            duration: fields[4].trim().parse().ok(),
+            mbid: fields.get(7).and_then(|mbid| non_empty(mbid)),
+            ..Scrobble::new(artist, track, when)
+        },
+        listened,
+    })
+}
+
+/// Clean up a log file after its entries were scrobbled
+pub fn finish_log(path: &Path, content: &str, after: AfterLog) -> anyhow::Result<()> {
+    match after {
+        AfterLog::Keep => {}
+        AfterLog::Truncate => {
+            let header: String = content
+                .lines()
+                .take_while(|line| line.starts_with('#'))
+                .map(|line| format!("{}\n", line))
+                .collect();
+            fs::write(path, header)?;
+        }
+        AfterLog::Archive => {
+            let suffix = OffsetDateTime::now_utc().format(format_description!(
+                "[year][month][day]-[hour][minute][second]"
+            ))?;
+            let mut archive_path = PathBuf::from(path).into_os_string();
+            archive_path.push(format!(".{}", suffix));
+            fs::rename(path, &archive_path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn test_parse_scrobbler_log() {
+        let content = "#AUDIOSCROBBLER/1.1\n\
+            #TZ/UTC\n\
+            #CLIENT/Rockbox sansae200 $Revision$\n\
+            Hooverphonic\tBlue Wonder Power Milk\tEden\t3\t241\tL\t1689478983\t\n\
+            Hooverphonic\tBlue Wonder Power Milk\tOne\t4\t220\tS\t1689479300\n\
+            Massive Attack\t\tTeardrop\t\t331\tL\t1689479600\t10b1ab49-6d2b-4eb1-8fbb-1fa0d4d5e1b3\n";
+        let entries = parse_scrobbler_log(content).unwrap();
+        assert_eq!(entries.len(), 3);
+
+        assert!(entries[0].listened);
+        assert_eq!(entries[0].scrobble.artist, "Hooverphonic");
+        assert_eq!(entries[0].scrobble.track, "Eden");
+        assert_eq!(
+            entries[0].scrobble.album.as_deref(),
+            Some("Blue Wonder Power Milk")
+        );
+        assert_eq!(entries[0].scrobble.track_number, Some(3));
+        assert_eq!(entries[0].scrobble.duration, Some(241));
+        assert_eq!(entries[0].scrobble.when.unix_timestamp(), 1689478983);
+        assert_eq!(entries[0].scrobble.mbid, None);
+
+        assert!(!entries[1].listened);
+
+        assert_eq!(entries[2].scrobble.album, None);
+        assert_eq!(entries[2].scrobble.track_number, None);
+        assert_eq!(
+            entries[2].scrobble.mbid.as_deref(),
+            Some("10b1ab49-6d2b-4eb1-8fbb-1fa0d4d5e1b3")
+        );
+    }
+
+    #[test]
+    fn test_parse_scrobbler_log_errors() {
+        assert!(parse_scrobbler_log("Hooverphonic\tEden").is_err());
+        assert!(parse_scrobbler_log("#AUDIOSCROBBLER/1.1\nHooverphonic\tEden\n").is_err());
+        assert!(parse_scrobbler_log(
+            "#AUDIOSCROBBLER/1.1\nHooverphonic\t\tEden\t1\t200\tX\t1689478983\n"
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,138 @@
+use anyhow::Context;
+use std::ops::RangeInclusive;
+
+/// Tracks of an album chosen for scrobbling, numbered from 1
+#[derive(Debug, Clone, Default)]
+pub struct TrackSelection {
+    /// Only these tracks, all tracks if not set
+    pub tracks: Option<Vec<RangeInclusive<usize>>>,
+    /// Tracks excluded from scrobbling
+    pub skip: Vec<RangeInclusive<usize>>,
+    /// First track to scrobble
+    pub from_track: Option<usize>,
+    /// Last track to scrobble
+    pub to_track: Option<usize>,
+    /// Only this disc of a multi-disc release, track numbers are counted within it
+    pub disc: Option<u32>,
+}
+
+impl TrackSelection {
+    /// Build a selection from command line arguments
+    pub fn parse(
+        tracks: Option<String>,
+        skip: Option<String>,
+        from_track: Option<usize>,
+        to_track: Option<usize>,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            tracks: tracks.as_deref().map(parse_track_ranges).transpose()?,
+            skip: skip
+                .as_deref()
+                .map(parse_track_ranges)
+                .transpose()?
+                .unwrap_or_default(),
+            from_track,
+            to_track,
+            disc: None,
+        })
+    }
+
+    /// Whether all tracks are selected
+    pub fn is_all(&self) -> bool {
+        self.tracks.is_none()
+            && self.skip.is_empty()
+            && self.from_track.is_none()
+            && self.to_track.is_none()
+            && self.disc.is_none()
+    }
+
+    /// Whether a track with given number is selected
+    pub fn contains(&self, number: usize) -> bool {
+        self.tracks
+            .as_ref()
+            .is_none_or(|tracks| tracks.iter().any(|range| range.contains(&number)))
+            && !self.skip.iter().any(|range| range.contains(&number))
+            && self.from_track.is_none_or(|from| number >= from)
+            && self.to_track.is_none_or(|to| number <= to)
+    }
+
+    /// Keep only selected items, numbering them from 1
+    pub fn apply<T>(&self, items: Vec<T>) -> Vec<T> {
+        items
+            .into_iter()
+            .enumerate()
+            .filter(|(idx, _)| self.contains(idx + 1))
+            .map(|(_, item)| item)
+            .collect()
+    }
+}
+
+/// Parse track ranges like "1-5,7", ranges are not expanded as they may be huge
+pub fn parse_track_ranges(arg: &str) -> anyhow::Result<Vec<RangeInclusive<usize>>> {
+    let mut ranges = Vec::new();
+    for part in arg.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let parse_number = |s: &str| -> anyhow::Result<usize> {
+            let number: usize = s
+                .trim()
+                .parse()
+                .with_context(|| format!("Wrong track number '{}'", s))?;
+            if number == 0 {
+                anyhow::bail!("Track numbers start from 1");
+            }
+            Ok(number)
+        };
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let first = parse_number(first)?;
+                let last = parse_number(last)?;
+                if first > last {
+                    anyhow::bail!("Wrong track range '{}'", part);
+                }
+                ranges.push(first..=last);
+            }
+            None => {
+                let number = parse_number(part)?;
+                ranges.push(number..=number);
+            }
+        }
+    }
+    if ranges.is_empty() {
+        anyhow::bail!("No tracks in '{}'", arg);
+    }
+    Ok(ranges)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn test_parse_track_ranges() {
+        assert_eq!(parse_track_ranges("1-5,7").unwrap(), vec![1..=5, 7..=7]);
+        assert_eq!(parse_track_ranges("3").unwrap(), vec![3..=3]);
+        assert_eq!(parse_track_ranges(" 2 , 4-4 ").unwrap(), vec![2..=2, 4..=4]);
+        assert!(parse_track_ranges("5-1").is_err());
+        assert!(parse_track_ranges("0").is_err());
+        assert!(parse_track_ranges("a").is_err());
+        assert!(parse_track_ranges("").is_err());
+    }
+
+    #[test]
+    fn test_selection() {
+        let selection =
+            TrackSelection::parse(Some("1-5,7".into()), Some("3".into()), Some(2), None).unwrap();
+        assert!(!selection.is_all());
+        let items: Vec<usize> = (1..=10).collect();
+        assert_eq!(selection.apply(items), vec![2, 4, 5, 7]);
+
+        let selection =
+            TrackSelection::parse(Some("2-4000000000".into()), None, None, None).unwrap();
+        assert_eq!(selection.apply(vec!["a", "b", "c"]), vec!["b", "c"]);
+
+        let selection = TrackSelection::parse(None, None, None, Some(2)).unwrap();
+        assert_eq!(selection.apply(vec!["a", "b", "c"]), vec!["a", "b"]);
+        assert!(TrackSelection::default().is_all());
+    }
+}
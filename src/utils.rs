@@ -1,5 +1,62 @@
-use time::OffsetDateTime;
+use anyhow::Context;
+use time::format_description::well_known::Rfc3339;
+use time::macros::format_description;
+use time::{OffsetDateTime, PrimitiveDateTime, UtcOffset};
 
 pub fn now_local() -> OffsetDateTime {
     OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc())
 }
+
+/// Parse RFC3339 or local date-time like "2026-10-15 21:30"
+pub fn parse_datetime(arg: &str) -> anyhow::Result<OffsetDateTime> {
+    let arg = arg.trim();
+    if let Ok(datetime) = OffsetDateTime::parse(arg, &Rfc3339) {
+        return Ok(datetime);
+    }
+    let local_formats = [
+        format_description!("[year]-[month]-[day] [hour]:[minute]:[second]"),
+        format_description!("[year]-[month]-[day] [hour]:[minute]"),
+        format_description!("[year]-[month]-[day]T[hour]:[minute]:[second]"),
+        format_description!("[year]-[month]-[day]T[hour]:[minute]"),
+    ];
+    let datetime = local_formats
+        .iter()
+        .find_map(|format| PrimitiveDateTime::parse(arg, format).ok())
+        .with_context(|| {
+            format!(
+                "Cannot parse date-time '{}', use RFC3339 or 'YYYY-MM-DD HH:MM'",
+                arg
+            )
+        })?;
+    Ok(assume_local(datetime))
+}
+
+/// Interpret a date-time without offset as a local one
+pub fn assume_local(datetime: PrimitiveDateTime) -> OffsetDateTime {
+    // Take the local offset at that moment to respect daylight saving changes
+    let offset =
+        UtcOffset::local_offset_at(datetime.assume_utc()).unwrap_or_else(|_| now_local().offset());
+    datetime.assume_offset(offset)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use test_log::test;
+    use time::macros::datetime;
+
+    #[test]
+    fn test_parse_datetime() {
+        assert_eq!(
+            parse_datetime("2026-10-15T21:30:00+02:00").unwrap(),
+            datetime!(2026-10-15 21:30 +2)
+        );
+        let local = parse_datetime("2026-10-15 21:30").unwrap();
+        assert_eq!(local.hour(), 21);
+        assert_eq!(local.minute(), 30);
+        assert_eq!(parse_datetime("2026-10-15T21:30:15").unwrap().second(), 15);
+        assert!(parse_datetime("yesterday").is_err());
+        assert!(parse_datetime("2026-13-01 10:00").is_err());
+    }
+}
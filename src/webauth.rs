@@ -0,0 +1,109 @@
+use anyhow::Context;
+use log::debug;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// Page shown in the browser after the redirect
+const DONE_PAGE: &str = "<html><body><h1>offline-scrobbler is authorized</h1>\
+    <p>You can close this window.</p></body></html>";
+
+/// Short-lived HTTP listener on localhost catching the web auth redirect with a token
+pub struct CallbackServer {
+    listener: TcpListener,
+    /// Random part of the callback path so that other local pages cannot guess it
+    nonce: String,
+}
+
+impl CallbackServer {
+    /// Listen on a free port of the loopback interface
+    pub fn bind() -> anyhow::Result<Self> {
+        let listener =
+            TcpListener::bind("127.0.0.1:0").context("Cannot start local callback server")?;
+        listener.set_nonblocking(true)?;
+        let mut nonce = [0u8; 16];
+        getrandom::getrandom(&mut nonce).context("Cannot generate callback nonce")?;
+        let nonce = nonce.iter().map(|byte| format!("{:02x}", byte)).collect();
+        Ok(Self { listener, nonce })
+    }
+
+    /// Path of the callback which is the only one answered
+    fn callback_path(&self) -> String {
+        format!("/callback/{}", self.nonce)
+    }
+
+    /// URL to pass as the `cb` parameter of web auth
+    pub fn callback_url(&self) -> anyhow::Result<String> {
+        Ok(format!(
+            "http://{}{}",
+            self.listener.local_addr()?,
+            self.callback_path()
+        ))
+    }
+
+    /// Wait for a callback request with a token, other requests like favicon are answered with 404
+    pub fn wait_for_token(&self, timeout: Duration) -> anyhow::Result<String> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.listener.accept() {
+                Ok((stream, addr)) => {
+                    debug!("Callback request from {}", addr);
+                    match handle_request(stream, &self.callback_path()) {
+                        Ok(Some(token)) => return Ok(token),
+                        Ok(None) => {}
+                        Err(e) => debug!("Bad callback request: {}", e),
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    if Instant::now() >= deadline {
+                        anyhow::bail!("No authorization from Last.fm within {:?}", timeout);
+                    }
+                    std::thread::sleep(Duration::from_millis(100));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+/// Read a request and answer it, returns a token if it is a GET of the callback path with one
+fn handle_request(mut stream: TcpStream, callback_path: &str) -> anyhow::Result<Option<String>> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Skip headers
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    // Request line is like "GET /callback/<nonce>?token=abc HTTP/1.1"
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().context("Wrong request line")?;
+    let target = parts.next().context("Wrong request line")?;
+    let url = Url::parse("http://localhost")?.join(target)?;
+    let token = url
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .map(|(_, value)| value.into_owned())
+        .filter(|token| !token.is_empty())
+        .filter(|_| method == "GET" && url.path() == callback_path);
+
+    let (status, body) = match token {
+        Some(_) => ("200 OK", DONE_PAGE),
+        None => ("404 Not Found", ""),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\n\
+        Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+    Ok(token)
+}